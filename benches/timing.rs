@@ -0,0 +1,40 @@
+#![feature(test)]
+
+extern crate chrono;
+extern crate habot;
+extern crate test;
+
+use chrono::Duration;
+use chrono::NaiveDate;
+use chrono::NaiveDateTime;
+use habot::models::NpcClass;
+use habot::timing::create_instances;
+use test::Bencher;
+
+fn class(next_tick: NaiveDateTime) -> NpcClass {
+    NpcClass {
+        id: 7,
+        name: "guard".to_string(),
+        commonality: 100,
+        next_tick,
+        active: 1,
+        unique: 0,
+        visit_minutes: 20,
+        world: 1,
+        announce: 0,
+        arrival_message: None,
+        departure_message: None,
+    }
+}
+
+// stepping through all 31 million one-second ticks would take minutes
+#[bench]
+fn catch_up_a_year(b: &mut Bencher) {
+    let tick = Duration::seconds(1);
+    let start = NaiveDate::from_ymd(2018, 9, 3).and_hms(0, 0, 0);
+    let final_time = start + Duration::days(365);
+    b.iter(|| {
+        let mut class = class(start);
+        create_instances(&mut class, &[], &[], &final_time, &tick)
+    });
+}
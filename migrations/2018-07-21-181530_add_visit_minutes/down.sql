@@ -0,0 +1,12 @@
+CREATE TABLE `npc_classes_old` (
+	`id`	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`name`	TEXT NOT NULL UNIQUE,
+	`commonality`	INTEGER NOT NULL,
+	`next_tick`	DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	`active`	INTEGER NOT NULL DEFAULT 1,
+	`unique`	INTEGER NOT NULL DEFAULT 0
+);
+INSERT INTO `npc_classes_old` (`id`, `name`, `commonality`, `next_tick`, `active`, `unique`)
+	SELECT `id`, `name`, `commonality`, `next_tick`, `active`, `unique` FROM `npc_classes`;
+DROP TABLE `npc_classes`;
+ALTER TABLE `npc_classes_old` RENAME TO `npc_classes`;
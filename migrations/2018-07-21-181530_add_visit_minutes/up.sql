@@ -0,0 +1 @@
+ALTER TABLE `npc_classes` ADD COLUMN `visit_minutes` INTEGER NOT NULL DEFAULT 20;
@@ -0,0 +1,8 @@
+CREATE TABLE `npc_schedules` (
+	`id`	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`class`	INTEGER NOT NULL,
+	`weekdays`	INTEGER NOT NULL DEFAULT 127,
+	`start_minute`	INTEGER NOT NULL,
+	`end_minute`	INTEGER NOT NULL,
+	FOREIGN KEY(`class`) REFERENCES `npc_classes`(`id`) ON DELETE CASCADE
+);
@@ -0,0 +1,48 @@
+CREATE TABLE `aliases_old` (
+	`id`	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`alias`	TEXT NOT NULL UNIQUE,
+	`command`	TEXT NOT NULL
+);
+INSERT INTO `aliases_old` (`id`, `alias`, `command`)
+	SELECT `id`, `alias`, `command` FROM `aliases` WHERE `world` = 1;
+DROP TABLE `aliases`;
+ALTER TABLE `aliases_old` RENAME TO `aliases`;
+
+CREATE TABLE `constants_old` (
+	`id`	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`key`	TEXT NOT NULL UNIQUE,
+	`value`	TEXT NOT NULL
+);
+INSERT INTO `constants_old` (`id`, `key`, `value`)
+	SELECT `id`, `key`, `value` FROM `constants` WHERE `world` = 1;
+DROP TABLE `constants`;
+ALTER TABLE `constants_old` RENAME TO `constants`;
+
+CREATE TABLE `npc_instances_old` (
+	`id`	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`class`	INTEGER NOT NULL,
+	`active_until`	DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	FOREIGN KEY(`class`) REFERENCES `npc_classes`(`id`) ON DELETE CASCADE
+);
+INSERT INTO `npc_instances_old` (`id`, `class`, `active_until`)
+	SELECT `id`, `class`, `active_until` FROM `npc_instances` WHERE `world` = 1;
+DROP TABLE `npc_instances`;
+ALTER TABLE `npc_instances_old` RENAME TO `npc_instances`;
+
+CREATE TABLE `npc_classes_old` (
+	`id`	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`name`	TEXT NOT NULL UNIQUE,
+	`commonality`	INTEGER NOT NULL,
+	`next_tick`	DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	`active`	INTEGER NOT NULL DEFAULT 1,
+	`unique`	INTEGER NOT NULL DEFAULT 0,
+	`visit_minutes`	INTEGER NOT NULL DEFAULT 20
+);
+INSERT INTO `npc_classes_old` (`id`, `name`, `commonality`, `next_tick`, `active`, `unique`, `visit_minutes`)
+	SELECT `id`, `name`, `commonality`, `next_tick`, `active`, `unique`, `visit_minutes` FROM `npc_classes` WHERE `world` = 1;
+DELETE FROM `npc_schedules` WHERE `class` NOT IN (SELECT `id` FROM `npc_classes_old`);
+DROP TABLE `npc_classes`;
+ALTER TABLE `npc_classes_old` RENAME TO `npc_classes`;
+
+DROP TABLE world_bindings;
+DROP TABLE worlds;
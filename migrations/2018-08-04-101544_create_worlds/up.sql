@@ -0,0 +1,72 @@
+-- Rebuilds tables that others reference. The migration runner turns foreign keys off
+-- around this and runs PRAGMA foreign_key_check afterwards, as sqlite documents.
+
+CREATE TABLE `worlds` (
+	`id`	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`name`	TEXT NOT NULL UNIQUE
+);
+
+INSERT INTO `worlds` (`id`, `name`) VALUES (1, 'default');
+
+CREATE TABLE `world_bindings` (
+	`id`	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`channel_id`	TEXT NOT NULL UNIQUE,
+	`world`	INTEGER NOT NULL,
+	FOREIGN KEY(`world`) REFERENCES `worlds`(`id`) ON DELETE CASCADE
+);
+
+CREATE TABLE `npc_classes_new` (
+	`id`	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`name`	TEXT NOT NULL,
+	`commonality`	INTEGER NOT NULL,
+	`next_tick`	DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	`active`	INTEGER NOT NULL DEFAULT 1,
+	`unique`	INTEGER NOT NULL DEFAULT 0,
+	`visit_minutes`	INTEGER NOT NULL DEFAULT 20,
+	`world`	INTEGER NOT NULL DEFAULT 1,
+	UNIQUE(`world`, `name`),
+	FOREIGN KEY(`world`) REFERENCES `worlds`(`id`) ON DELETE CASCADE
+);
+INSERT INTO `npc_classes_new` (`id`, `name`, `commonality`, `next_tick`, `active`, `unique`, `visit_minutes`, `world`)
+	SELECT `id`, `name`, `commonality`, `next_tick`, `active`, `unique`, `visit_minutes`, 1 FROM `npc_classes`;
+DROP TABLE `npc_classes`;
+ALTER TABLE `npc_classes_new` RENAME TO `npc_classes`;
+
+CREATE TABLE `npc_instances_new` (
+	`id`	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`class`	INTEGER NOT NULL,
+	`active_until`	DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	`world`	INTEGER NOT NULL DEFAULT 1,
+	FOREIGN KEY(`class`) REFERENCES `npc_classes`(`id`) ON DELETE CASCADE,
+	FOREIGN KEY(`world`) REFERENCES `worlds`(`id`) ON DELETE CASCADE
+);
+INSERT INTO `npc_instances_new` (`id`, `class`, `active_until`, `world`)
+	SELECT `id`, `class`, `active_until`, 1 FROM `npc_instances`;
+DROP TABLE `npc_instances`;
+ALTER TABLE `npc_instances_new` RENAME TO `npc_instances`;
+
+CREATE TABLE `constants_new` (
+	`id`	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`key`	TEXT NOT NULL,
+	`value`	TEXT NOT NULL,
+	`world`	INTEGER NOT NULL DEFAULT 1,
+	UNIQUE(`world`, `key`),
+	FOREIGN KEY(`world`) REFERENCES `worlds`(`id`) ON DELETE CASCADE
+);
+INSERT INTO `constants_new` (`id`, `key`, `value`, `world`)
+	SELECT `id`, `key`, `value`, 1 FROM `constants`;
+DROP TABLE `constants`;
+ALTER TABLE `constants_new` RENAME TO `constants`;
+
+CREATE TABLE `aliases_new` (
+	`id`	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`alias`	TEXT NOT NULL,
+	`command`	TEXT NOT NULL,
+	`world`	INTEGER NOT NULL DEFAULT 1,
+	UNIQUE(`world`, `alias`),
+	FOREIGN KEY(`world`) REFERENCES `worlds`(`id`) ON DELETE CASCADE
+);
+INSERT INTO `aliases_new` (`id`, `alias`, `command`, `world`)
+	SELECT `id`, `alias`, `command`, 1 FROM `aliases`;
+DROP TABLE `aliases`;
+ALTER TABLE `aliases_new` RENAME TO `aliases`;
@@ -0,0 +1,9 @@
+CREATE TABLE `guild_settings` (
+	`id`	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`guild_id`	TEXT NOT NULL UNIQUE,
+	`prefix`	TEXT,
+	`admin_role`	TEXT,
+	`world`	INTEGER,
+	`output_channel`	TEXT,
+	FOREIGN KEY(`world`) REFERENCES `worlds`(`id`) ON DELETE SET NULL
+);
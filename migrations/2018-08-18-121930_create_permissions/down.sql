@@ -0,0 +1,9 @@
+CREATE TABLE `admins` (
+	`id`	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`user_id`	TEXT NOT NULL UNIQUE
+);
+
+INSERT INTO `admins` (`user_id`)
+	SELECT `subject` FROM `permissions` WHERE `kind` = 'user' AND `level` = 'owner';
+
+DROP TABLE permissions;
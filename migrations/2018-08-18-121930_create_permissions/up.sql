@@ -0,0 +1,12 @@
+CREATE TABLE `permissions` (
+	`id`	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`kind`	TEXT NOT NULL,
+	`subject`	TEXT NOT NULL,
+	`level`	TEXT NOT NULL,
+	UNIQUE(`kind`, `subject`)
+);
+
+INSERT INTO `permissions` (`kind`, `subject`, `level`)
+	SELECT 'user', `user_id`, 'owner' FROM `admins`;
+
+DROP TABLE `admins`;
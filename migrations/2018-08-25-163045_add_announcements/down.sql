@@ -0,0 +1,16 @@
+CREATE TABLE `npc_classes_old` (
+	`id`	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`name`	TEXT NOT NULL,
+	`commonality`	INTEGER NOT NULL,
+	`next_tick`	DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	`active`	INTEGER NOT NULL DEFAULT 1,
+	`unique`	INTEGER NOT NULL DEFAULT 0,
+	`visit_minutes`	INTEGER NOT NULL DEFAULT 20,
+	`world`	INTEGER NOT NULL DEFAULT 1,
+	UNIQUE(`world`, `name`),
+	FOREIGN KEY(`world`) REFERENCES `worlds`(`id`) ON DELETE CASCADE
+);
+INSERT INTO `npc_classes_old` (`id`, `name`, `commonality`, `next_tick`, `active`, `unique`, `visit_minutes`, `world`)
+	SELECT `id`, `name`, `commonality`, `next_tick`, `active`, `unique`, `visit_minutes`, `world` FROM `npc_classes`;
+DROP TABLE `npc_classes`;
+ALTER TABLE `npc_classes_old` RENAME TO `npc_classes`;
@@ -0,0 +1,3 @@
+ALTER TABLE `npc_classes` ADD COLUMN `announce` INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE `npc_classes` ADD COLUMN `arrival_message` TEXT;
+ALTER TABLE `npc_classes` ADD COLUMN `departure_message` TEXT;
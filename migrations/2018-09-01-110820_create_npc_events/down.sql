@@ -0,0 +1 @@
+DROP TABLE `npc_events`;
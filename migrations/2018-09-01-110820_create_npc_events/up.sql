@@ -0,0 +1,11 @@
+CREATE TABLE `npc_events` (
+	`id`	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`world`	INTEGER NOT NULL,
+	`kind`	TEXT NOT NULL,
+	`class`	INTEGER NOT NULL,
+	`class_name`	TEXT NOT NULL,
+	`instance`	INTEGER NOT NULL,
+	`time`	DATETIME NOT NULL,
+	FOREIGN KEY(`world`) REFERENCES `worlds`(`id`) ON DELETE CASCADE
+);
+CREATE INDEX `npc_events_world_time` ON `npc_events` (`world`, `time`);
@@ -0,0 +1,12 @@
+CREATE TABLE `npc_instances_old` (
+	`id`	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`class`	INTEGER NOT NULL,
+	`active_until`	DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	`world`	INTEGER NOT NULL DEFAULT 1,
+	FOREIGN KEY(`class`) REFERENCES `npc_classes`(`id`) ON DELETE CASCADE,
+	FOREIGN KEY(`world`) REFERENCES `worlds`(`id`) ON DELETE CASCADE
+);
+INSERT INTO `npc_instances_old` (`id`, `class`, `active_until`, `world`)
+	SELECT `id`, `class`, `active_until`, `world` FROM `npc_instances`;
+DROP TABLE `npc_instances`;
+ALTER TABLE `npc_instances_old` RENAME TO `npc_instances`;
@@ -0,0 +1,3 @@
+ALTER TABLE `npc_instances` ADD COLUMN `name` TEXT;
+ALTER TABLE `npc_instances` ADD COLUMN `notes` TEXT;
+ALTER TABLE `npc_instances` ADD COLUMN `attributes` TEXT NOT NULL DEFAULT '{}';
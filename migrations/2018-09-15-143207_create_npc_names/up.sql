@@ -0,0 +1,8 @@
+CREATE TABLE `npc_names` (
+	`id`	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`class`	INTEGER NOT NULL,
+	`kind`	TEXT NOT NULL,
+	`value`	TEXT NOT NULL,
+	UNIQUE(`class`, `kind`, `value`),
+	FOREIGN KEY(`class`) REFERENCES `npc_classes`(`id`) ON DELETE CASCADE
+);
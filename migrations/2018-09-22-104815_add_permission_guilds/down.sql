@@ -0,0 +1,12 @@
+-- grants made in a guild can not be kept without widening them to every guild
+CREATE TABLE `permissions_new` (
+	`id`	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`kind`	TEXT NOT NULL,
+	`subject`	TEXT NOT NULL,
+	`level`	TEXT NOT NULL,
+	UNIQUE(`kind`, `subject`)
+);
+INSERT INTO `permissions_new` (`id`, `kind`, `subject`, `level`)
+	SELECT `id`, `kind`, `subject`, `level` FROM `permissions` WHERE `guild` IS NULL;
+DROP TABLE `permissions`;
+ALTER TABLE `permissions_new` RENAME TO `permissions`;
@@ -0,0 +1,12 @@
+CREATE TABLE `permissions_new` (
+	`id`	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`kind`	TEXT NOT NULL,
+	`subject`	TEXT NOT NULL,
+	`level`	TEXT NOT NULL,
+	`guild`	TEXT,
+	UNIQUE(`kind`, `subject`, `guild`)
+);
+INSERT INTO `permissions_new` (`id`, `kind`, `subject`, `level`)
+	SELECT `id`, `kind`, `subject`, `level` FROM `permissions`;
+DROP TABLE `permissions`;
+ALTER TABLE `permissions_new` RENAME TO `permissions`;
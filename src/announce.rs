@@ -0,0 +1,117 @@
+use diesel;
+use diesel::sqlite::SqliteConnection;
+use error::Error;
+use queries::get_classes;
+use queries::get_events_after;
+use queries::get_last_event;
+use queries::get_worlds;
+use settings::get_setting;
+use settings::parse_channel;
+use std::collections::HashMap;
+use timing::arrives;
+use timing::update_instances;
+
+pub struct Announcement {
+    pub channel: u64,
+    pub text: String,
+}
+
+/// Runs the timing update for every world with an announce channel and turns
+/// the logged events of opted-in classes into messages. Reading the event log
+/// also catches updates made by a `show instance` between two polls.
+#[derive(Default)]
+pub struct Announcer {
+    seen: HashMap<i32, i32>,
+}
+
+impl Announcer {
+    /// Returns the announcements, and the worlds that failed with their errors
+    /// so one broken world does not silence the others.
+    pub fn poll(
+        &mut self,
+        connection: &SqliteConnection,
+    ) -> Result<(Vec<Announcement>, Vec<(String, Error)>), Error> {
+        let mut result = Vec::new();
+        let mut failures = Vec::new();
+        for world in get_worlds(connection)? {
+            match self.poll_world(connection, world.id) {
+                Ok(mut announcements) => result.append(&mut announcements),
+                Err(e) => failures.push((world.name, e)),
+            }
+        }
+        Ok((result, failures))
+    }
+
+    fn poll_world(
+        &mut self,
+        connection: &SqliteConnection,
+        world: i32,
+    ) -> Result<Vec<Announcement>, Error> {
+        let channel = match get_setting(connection, world, "announce_channel")? {
+            Some(value) => parse_channel(&value)?,
+            None => None,
+        };
+        let channel = match channel {
+            Some(channel) => channel,
+            None => {
+                self.seen.remove(&world);
+                return Ok(Vec::new());
+            }
+        };
+
+        update(connection, world)?;
+        // the first poll of a world only records where the log ends
+        let last = match self.seen.get(&world) {
+            Some(&last) => last,
+            None => {
+                self.seen.insert(world, get_last_event(connection, world)?);
+                return Ok(Vec::new());
+            }
+        };
+        let events = get_events_after(connection, world, last)?;
+        if let Some(event) = events.last() {
+            self.seen.insert(world, event.id);
+        }
+
+        let classes = get_classes(connection, world)?
+            .into_iter()
+            .map(|class| (class.id, class))
+            .collect::<HashMap<_, _>>();
+        let arrival = get_setting(connection, world, "arrival_message")?;
+        let departure = get_setting(connection, world, "departure_message")?;
+        let mut result = Vec::new();
+        for event in &events {
+            let class = match classes.get(&event.class) {
+                Some(class) if class.announce > 0 => class,
+                _ => continue,
+            };
+            let template = match event.kind.as_ref() {
+                "expired" => class.departure_message.as_ref().or(departure.as_ref()),
+                "spawned" if arrives(&events, event) => {
+                    class.arrival_message.as_ref().or(arrival.as_ref())
+                }
+                _ => None,
+            };
+            if let Some(template) = template {
+                result.push(Announcement {
+                    channel,
+                    text: template.replace("{class}", &class.name),
+                });
+            }
+        }
+        Ok(result)
+    }
+}
+
+fn update(connection: &SqliteConnection, world: i32) -> Result<(), Error> {
+    let mut failure = None;
+    connection
+        .immediate_transaction::<_, diesel::result::Error, _>(|| {
+            update_instances(connection, world).map_err(|e| {
+                failure = Some(e);
+                diesel::result::Error::RollbackTransaction
+            })
+        })
+        .map(|_report| ())
+        .map_err(|e| failure.unwrap_or_else(|| Error::database("could not update npcs", e)))
+}
@@ -4,76 +4,172 @@ extern crate serenity;
 extern crate shlex;
 
 use diesel::sqlite::SqliteConnection;
+use habot::bulk::BulkFormat;
+use habot::announce::Announcer;
 use habot::command::parse_aliased;
-use habot::establish_connection;
+use habot::config::Config;
+use habot::error::Error;
+use habot::establish_pool;
 use habot::execute::execute_command;
+use habot::execute::Scope;
+use habot::migrate::run_pending;
+use habot::output::render_discord;
+use habot::output::CommandOutput;
+use habot::permissions::Level;
 use habot::queries::get_aliases;
-use habot::queries::get_constant;
+use habot::queries::get_guild_settings;
+use habot::queries::resolve_world;
+use habot::queries::user_level;
+use habot::settings::get_setting;
+use habot::Pool;
 use serenity::client::Context;
 use serenity::client::EventHandler;
+use serenity::model::channel::Attachment;
 use serenity::model::channel::Message;
 use serenity::model::gateway::Ready;
+use serenity::model::id::ChannelId;
 use serenity::Client;
 use std::env;
-use serenity::model::user::User;
-use habot::queries::is_admin;
+use std::process;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
 
 struct Handler {
-    url: String,
+    pool: Pool,
     starter: String,
 }
 
 impl Handler {
-    fn process(&self, connection: &SqliteConnection, text: String, author: User) -> Result<String, String> {
-        let args = shlex::split(&text).ok_or_else(|| "malformed arguments string".to_string())?;
-        let aliases = get_aliases(connection)?;
+    fn process(
+        &self,
+        connection: &SqliteConnection,
+        scope: &Scope,
+        text: String,
+        author: u64,
+        roles: &[u64],
+        role_admin: bool,
+        attachments: &[Attachment],
+    ) -> Result<CommandOutput, Error> {
+        let mut args = shlex::split(&text)
+            .ok_or_else(|| Error::Parse("malformed arguments string".to_string()))?;
+        if args.len() == 2 && args[0] == "import" {
+            let (_name, document) = download(attachments, "snapshot", "import")?;
+            args.push(document);
+        }
+        // the format may be given before the attachment, otherwise its extension decides
+        if (args.len() == 2 || args.len() == 3) && args[0] == "add" && args[1] == "classes" {
+            let (name, document) = download(attachments, "class file", "add classes")?;
+            if args.len() == 2 {
+                args.push(BulkFormat::from_filename(&name).map(|f| f.extension())?.to_string());
+            }
+            args.push(document);
+        }
+        let aliases = get_aliases(connection, scope.world.id)?;
         let cmd = parse_aliased(args, aliases)?;
-        if !cmd.is_public() && !is_admin(connection, author.id.0)? {
-            Err("permission denied".to_string())
+        // the admin role and grants made in this guild only reach as far as the guild
+        let global = user_level(connection, None, author, roles)?;
+        let level = if role_admin {
+            Level::Owner
         } else {
-            execute_command(connection, cmd)
+            user_level(connection, scope.guild, author, roles)?
+        };
+        if level < cmd.required_level() {
+            return Err(Error::Permission(format!(
+                "permission denied: {} level required",
+                cmd.required_level()
+            )));
+        }
+        if global < cmd.required_level() && !cmd.within_world(&scope.world.name) {
+            return Err(Error::Permission(format!(
+                "permission denied: {} level granted outside of this guild required",
+                cmd.required_level()
+            )));
+        }
+        if let Some(channel) = cmd.target_channel() {
+            if channel_guild(channel)? != scope.guild {
+                return Err(Error::Permission(format!(
+                    "channel {} is not in this guild",
+                    channel
+                )));
+            }
         }
+        execute_command(connection, scope, cmd)
     }
 }
 
 impl EventHandler for Handler {
     fn message(&self, _: Context, msg: Message) {
-        let connection = match establish_connection(&self.url) {
+        let connection = match self.pool.get() {
             Ok(c) => c,
             Err(e) => {
-                println!("could not connect to database: {}", e);
+                println!("could not get a database connection: {}", e);
+                return;
+            }
+        };
+
+        let guild = msg.guild_id().map(|g| g.0);
+        let settings = match guild.map(|g| get_guild_settings(&connection, g)) {
+            None => None,
+            Some(Ok(s)) => s,
+            Some(Err(e)) => {
+                println!("{}", e);
                 return;
             }
         };
 
-        let starter = match get_constant(&connection, "starter".to_string()) {
-            Ok(s) => s.map(|c| c.value).unwrap_or_else(|| self.starter.clone()),
+        let world = match resolve_world(&connection, Some(msg.channel_id.0), guild) {
+            Ok(w) => w,
             Err(e) => {
                 println!("{}", e);
                 return;
             }
         };
 
-        if let Some(text) = try_strip_of(&starter, &msg.content) {
-            let text = match self.process(&connection, text, msg.author) {
-                Err(e) => format!("Error: {}", e),
-                Ok(r) => r,
-            };
-            let result = if text.len() < 2000 {
-                msg.channel_id.say(text).map(|_| ())
-            } else {
-                let parts = chunk_lines(text);
-                let mut result = Ok(());
-                for part in parts {
-                    match msg.channel_id.say(part) {
-                        Ok(_) => {}
-                        Err(err) => {
-                            result = Err(err);
-                            break;
-                        }
-                    }
+        let starter = match settings.as_ref().and_then(|s| s.prefix.clone()) {
+            Some(prefix) => prefix,
+            None => match get_setting(&connection, world.id, "starter") {
+                Ok(s) => s.unwrap_or_else(|| self.starter.clone()),
+                Err(e) => {
+                    println!("{}", e);
+                    return;
                 }
-                result
+            },
+        };
+
+        if let Some(text) = try_strip_of(&starter, &msg.content) {
+            let roles = msg
+                .member()
+                .or_else(|| msg.guild_id().and_then(|g| g.member(msg.author.id).ok()))
+                .map_or_else(Vec::new, |m| m.roles.iter().map(|r| r.0).collect());
+            let role_admin = settings
+                .as_ref()
+                .and_then(|s| s.admin_role.as_ref())
+                .and_then(|r| u64::from_str(r).ok())
+                .map_or(false, |role| roles.contains(&role));
+            let output = settings
+                .as_ref()
+                .and_then(|s| s.output_channel.as_ref())
+                .and_then(|c| u64::from_str(c).ok())
+                .map(ChannelId)
+                .unwrap_or(msg.channel_id);
+            let scope = Scope { world, guild };
+            let result = match self.process(
+                &connection,
+                &scope,
+                text,
+                msg.author.id.0,
+                &roles,
+                role_admin,
+                &msg.attachments,
+            ) {
+                Err(e) => send_text(output, describe(e)),
+                Ok(CommandOutput::Document { name, content }) => output
+                    .send_files(vec![(content.as_bytes(), name.as_str())], |m| {
+                        m.content(&name)
+                    })
+                    .map(|_| ()),
+                Ok(r) => send_text(output, render_discord(&r)),
             };
             match result {
                 Ok(_) => {}
@@ -87,6 +183,78 @@ impl EventHandler for Handler {
     }
 }
 
+/// Keeps npcs up to date without anyone asking and posts the announcements.
+fn announce(pool: Pool, interval: Duration) {
+    let mut announcer = Announcer::default();
+    loop {
+        let result = pool
+            .get()
+            .map_err(|e| Error::Connection(format!("could not get a database connection: {}", e)))
+            .and_then(|connection| announcer.poll(&connection));
+        match result {
+            Ok((announcements, failures)) => {
+                for (world, e) in failures {
+                    println!("could not update npcs of world {}: {}", world, e);
+                }
+                for announcement in announcements {
+                    if let Err(e) = ChannelId(announcement.channel).say(&announcement.text) {
+                        println!("could not send announcement: {}", e);
+                    }
+                }
+            }
+            Err(e) => println!("could not update npcs: {}", e),
+        }
+        thread::sleep(interval);
+    }
+}
+
+fn send_text(channel: ChannelId, text: String) -> serenity::Result<()> {
+    if text.len() < 2000 {
+        return channel.say(text).map(|_| ());
+    }
+    for part in chunk_lines(text) {
+        channel.say(part)?;
+    }
+    Ok(())
+}
+
+/// Fetches the first attachment, returning its file name and text.
+fn download(
+    attachments: &[Attachment],
+    what: &str,
+    command: &str,
+) -> Result<(String, String), Error> {
+    let attachment = attachments.first().ok_or_else(|| {
+        Error::Parse(format!("attach the {} to the {} command", what, command))
+    })?;
+    let bytes = attachment.download().map_err(|e| {
+        Error::Connection(format!("could not download {}: {}", attachment.filename, e))
+    })?;
+    let document = String::from_utf8(bytes)
+        .map_err(|e| Error::Parse(format!("{} is not valid utf-8: {}", what, e)))?;
+    Ok((attachment.filename.clone(), document))
+}
+
+/// Looks up the guild of a channel, none for private channels.
+fn channel_guild(channel: u64) -> Result<Option<u64>, Error> {
+    let channel = ChannelId(channel)
+        .get()
+        .map_err(|e| Error::NotFound(format!("could not find channel {}: {}", channel, e)))?;
+    Ok(channel.guild().map(|c| c.read().guild_id.0))
+}
+
+/// Hides storage details from the channel; they only go to the bot log.
+fn describe(error: Error) -> String {
+    match error {
+        Error::Locked(_) => "Error: the database is busy, try again in a moment".to_string(),
+        Error::Database(_) | Error::SchemaViolation(_) | Error::Connection(_) => {
+            println!("{}", error);
+            "Error: something went wrong with the database, see the bot log".to_string()
+        }
+        error => format!("Error: {}", error),
+    }
+}
+
 fn try_strip_of(starter: &str, text: &str) -> Option<String> {
     let mut parts = text.splitn(2, &starter);
     match parts.next().map(str::is_empty) {
@@ -95,20 +263,53 @@ fn try_strip_of(starter: &str, text: &str) -> Option<String> {
     }
 }
 
-fn main() -> Result<(), String> {
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        process::exit(e.exit_code());
+    }
+}
+
+fn run() -> Result<(), Error> {
     let mut args = env::args().collect::<Vec<_>>();
     args.remove(0);
-    let url = args.remove(0);
+    let path = match args.get(0).map(String::as_ref) {
+        Some("--config") => Some(
+            args.get(1)
+                .cloned()
+                .ok_or_else(|| Error::Parse("missing config path".to_string()))?,
+        ),
+        Some(other) => return Err(Error::Parse(format!("unknown argument: {}", other))),
+        None => None,
+    };
+    if let Some(extra) = args.get(2) {
+        return Err(Error::Parse(format!("unexpected argument: {}", extra)));
+    }
+    let config = Config::load(path.as_ref().map(String::as_ref))?;
+    let token = config.token()?.to_string();
+    let pool = establish_pool(config.database_url()?)?;
+    {
+        // returned to the pool at the end of the block, the handler needs all of them
+        let connection = pool.get().map_err(|e| {
+            Error::Connection(format!("could not get a database connection: {}", e))
+        })?;
+        for name in run_pending(&connection)? {
+            println!("applied migration {}", name);
+        }
+    }
+    let announcer_pool = pool.clone();
     let handler = Handler {
-        url,
-        starter: "!".to_string(),
+        pool,
+        starter: config.prefix().to_string(),
     };
 
-    let token = "NDY2MTY3NzM4OTEwNjM4MDgw.DiYIKQ.ScJVGtK4HL4CSdTsrrrhFWKjxmg";
-    let mut client = Client::new(token, handler).expect("Err creating client");
+    let mut client = Client::new(&token, handler)
+        .map_err(|e| Error::Connection(format!("could not create client: {}", e.to_string())))?;
+    let interval = config.announce_interval();
+    thread::spawn(move || announce(announcer_pool, interval));
     client
         .start()
-        .map_err(|e| format!("could not start bot: {}", e.to_string()))?;
+        .map_err(|e| Error::Connection(format!("could not start bot: {}", e.to_string())))?;
     Ok(())
 }
 
@@ -1,32 +1,333 @@
+extern crate diesel;
 extern crate habot;
+extern crate rustyline;
+extern crate shlex;
 
-use habot::command::parse_command;
+use diesel::sqlite::SqliteConnection;
+use habot::bulk::BulkFormat;
+use habot::command::parse_aliased;
+use habot::config::Config;
+use habot::error::Error;
 use habot::establish_connection;
 use habot::execute::execute_command;
+use habot::execute::Scope;
+use habot::migrate::revert_latest;
+use habot::migrate::run_pending;
+use habot::migrate::status;
+use habot::output::render;
+use habot::output::CommandOutput;
+use habot::output::Format;
+use habot::queries::get_aliases;
+use habot::queries::get_classes;
+use habot::queries::get_world;
+use habot::queries::resolve_world;
+use habot::settings::SETTINGS;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::Editor;
+use rustyline::Helper;
 use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
 
-fn main() -> Result<(), String> {
+const GROUPS: &[&str] = &[
+    "show",
+    "add",
+    "remove",
+    "change",
+    "fast-forward",
+    "pause",
+    "resume",
+    "export",
+    "import",
+    "exit",
+];
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        process::exit(e.exit_code());
+    }
+}
+
+fn run() -> Result<(), Error> {
     let mut args = env::args().collect::<Vec<_>>();
     args.remove(0);
 
-    let url = args.remove(0);
-    let connection = establish_connection(&url)?;
+    let mut path = None;
+    let mut url = None;
+    let mut world = None;
+    let mut format = Format::Text;
+    loop {
+        match args.get(0).map(String::as_ref) {
+            Some("--config") | Some("--database") | Some("--world") | Some("--format") => {}
+            _ => break,
+        }
+        let flag = args.remove(0);
+        if args.is_empty() {
+            return Err(Error::Parse(format!("missing value for {}", flag)));
+        }
+        let value = args.remove(0);
+        match flag.as_ref() {
+            "--config" => path = Some(value),
+            "--database" => url = Some(value),
+            "--format" => format = Format::from_str(&value)?,
+            _ => world = Some(value),
+        }
+    }
+    let mut config = Config::load(path.as_ref().map(String::as_ref))?;
+    if url.is_some() {
+        config.database_url = url;
+    }
+    let connection = establish_connection(config.database_url()?)?;
+    if args.get(0).map(String::as_ref) == Some("migrate") {
+        return migrate(&connection, &args[1..]);
+    }
+    for name in run_pending(&connection)? {
+        eprintln!("applied migration {}", name);
+    }
+
+    let world = match world {
+        Some(name) => get_world(&connection, name)?,
+        None => resolve_world(&connection, None, None)?,
+    };
+    let scope = Scope { world, guild: None };
 
-    let command = parse_command(args);
+    if args.is_empty() {
+        return repl(&connection, &scope, format);
+    }
+    let output = run_command(&connection, &scope, args)?;
+    println!("{}", render(&output, format)?);
+    Ok(())
+}
 
-    let result = match command {
-        Err(e) => {
-            return Err(e);
+fn migrate(connection: &SqliteConnection, args: &[String]) -> Result<(), Error> {
+    match args.get(0).map(String::as_ref) {
+        Some("status") => {
+            for (name, applied) in status(connection)? {
+                println!("{} {}", if applied { "applied" } else { "pending" }, name);
+            }
         }
-        Ok(command) => execute_command(&connection, command),
-    };
+        Some("up") => {
+            let applied = run_pending(connection)?;
+            if applied.is_empty() {
+                println!("no pending migrations");
+            }
+            for name in applied {
+                println!("applied {}", name);
+            }
+        }
+        Some("down") => println!("reverted {}", revert_latest(connection)?),
+        _ => {
+            return Err(Error::Parse(
+                "available commands: migrate status | up | down".to_string(),
+            ))
+        }
+    }
+    Ok(())
+}
 
-    match result {
-        Err(e) => {
-            return Err(e);
+fn run_command(
+    connection: &SqliteConnection,
+    scope: &Scope,
+    mut args: Vec<String>,
+) -> Result<CommandOutput, Error> {
+    if args.len() == 3 && args[0] == "import" {
+        let path = args.pop().unwrap_or_default();
+        args.push(read_file(&path, "snapshot")?);
+    }
+    // the format may be given before the path, otherwise its extension decides
+    if (args.len() == 3 || args.len() == 4) && args[0] == "add" && args[1] == "classes" {
+        let path = args.pop().unwrap_or_default();
+        if args.len() == 2 {
+            args.push(BulkFormat::from_filename(&path)?.extension().to_string());
         }
-        Ok(result) => println!("{}", result),
+        args.push(read_file(&path, "class file")?);
     }
+    let aliases = get_aliases(connection, scope.world.id)?;
+    let command = parse_aliased(args, aliases)?;
+    execute_command(connection, scope, command)
+}
+
+fn read_file(path: &str, what: &str) -> Result<String, Error> {
+    let mut document = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut document))
+        .map_err(|e| Error::Invalid(format!("could not read {} {}: {}", what, path, e)))?;
+    Ok(document)
+}
 
+fn repl(connection: &SqliteConnection, scope: &Scope, format: Format) -> Result<(), Error> {
+    let mut editor = Editor::<Completion>::new();
+    editor.set_helper(Some(Completion {
+        connection,
+        world: scope.world.id,
+    }));
+    let history = env::var("HOME")
+        .ok()
+        .map(|home| PathBuf::from(home).join(".habot_history"));
+    if let Some(ref path) = history {
+        let _ = editor.load_history(path);
+    }
+
+    let prompt = format!("{}> ", scope.world.name);
+    loop {
+        let line = match editor.readline(&prompt) {
+            Ok(line) => line.trim().to_string(),
+            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(Error::Invalid(format!("could not read input: {}", e))),
+        };
+        if line.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line.as_str());
+        if line == "exit" {
+            break;
+        }
+        let result = shlex::split(&line)
+            .ok_or_else(|| Error::Parse("malformed arguments string".to_string()))
+            .and_then(|args| run_command(connection, scope, args))
+            .and_then(|output| render(&output, format));
+        match result {
+            Ok(text) => println!("{}", text),
+            Err(e) => eprintln!("Error: {}", e),
+        }
+    }
+
+    if let Some(ref path) = history {
+        if let Err(e) = editor.save_history(path) {
+            eprintln!("could not save history: {}", e);
+        }
+    }
     Ok(())
 }
+
+/// Completes command groups and aliases, their targets and keys, and class names.
+struct Completion<'a> {
+    connection: &'a SqliteConnection,
+    world: i32,
+}
+
+impl<'a> Completion<'a> {
+    fn candidates(&self, words: &[&str]) -> Vec<String> {
+        let fixed: &[&str] = match words {
+            [] => {
+                let mut result = GROUPS.iter().map(|g| g.to_string()).collect::<Vec<_>>();
+                if let Ok(aliases) = get_aliases(self.connection, self.world) {
+                    result.extend(aliases.into_iter().map(|(alias, _command)| alias));
+                }
+                return result;
+            }
+            ["show"] => &[
+                "instance",
+                "changes",
+                "history",
+                "stats",
+                "class",
+                "schedule",
+                "names",
+                "clock",
+                "world",
+                "guild",
+                "permission",
+                "alias",
+                "setting",
+            ],
+            ["add"] => &[
+                "class",
+                "classes",
+                "schedule",
+                "name",
+                "syllable",
+                "alias",
+                "world",
+                "binding",
+                "permission",
+            ],
+            ["remove"] => &[
+                "class",
+                "instance",
+                "all_instances",
+                "schedule",
+                "name",
+                "syllable",
+                "alias",
+                "world",
+                "binding",
+                "permission",
+            ],
+            ["change"] => &[
+                "class", "instance", "starter", "tick", "setting", "clock", "guild",
+            ],
+            ["change", "setting"] => return SETTINGS.iter().map(|s| s.key.to_string()).collect(),
+            ["show", "instance"] => &["verbose"],
+            ["show", "changes"] => &["since"],
+            ["export"] => &["json", "toml"],
+            ["export", _] => &["instances"],
+            ["import"] => &["merge", "replace"],
+            ["show", "schedule"]
+            | ["show", "names"]
+            | ["add", "schedule"]
+            | ["add", "name"]
+            | ["add", "syllable"]
+            | ["remove", "name"]
+            | ["remove", "syllable"]
+            | ["remove", "class"]
+            | ["change", "class"] => return self.class_names(),
+            ["change", "class", _] => &[
+                "name",
+                "freq",
+                "active",
+                "unique",
+                "visit",
+                "announce",
+                "arrival",
+                "departure",
+            ],
+            ["change", "instance", _] => &["name", "note", "attribute"],
+            ["change", "clock"] => &["rate", "time"],
+            ["change", "guild"] => &["prefix", "role", "world", "channel"],
+            _ => &[],
+        };
+        fixed.iter().map(|c| c.to_string()).collect()
+    }
+
+    fn class_names(&self) -> Vec<String> {
+        get_classes(self.connection, self.world)
+            .map(|classes| classes.into_iter().map(|c| c.name).collect())
+            .unwrap_or_else(|_| Vec::new())
+    }
+}
+
+impl<'a> Completer for Completion<'a> {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize) -> rustyline::Result<(usize, Vec<String>)> {
+        let before = &line[..pos];
+        let start = before.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let words = before[..start].split_whitespace().collect::<Vec<_>>();
+        let partial = &before[start..];
+        let mut candidates = self
+            .candidates(&words)
+            .into_iter()
+            .filter(|c| c.starts_with(partial))
+            .collect::<Vec<_>>();
+        candidates.sort();
+        Ok((start, candidates))
+    }
+}
+
+impl<'a> Hinter for Completion<'a> {
+    fn hint(&self, _line: &str, _pos: usize) -> Option<String> {
+        None
+    }
+}
+
+impl<'a> Highlighter for Completion<'a> {}
+
+impl<'a> Helper for Completion<'a> {}
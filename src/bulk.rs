@@ -0,0 +1,247 @@
+use chrono::Duration;
+use csv;
+use diesel;
+use diesel::sqlite::SqliteConnection;
+use diesel::Connection;
+use error::Error;
+use queries::add_class;
+use queries::add_schedule;
+use queries::change_announce;
+use queries::get_classes;
+use schedule::parse_time;
+use schedule::parse_weekdays;
+use serde_yaml;
+use std::str::FromStr;
+use timing::check_rate;
+use timing::get_tick;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum BulkFormat {
+    Csv,
+    Yaml,
+}
+
+impl BulkFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            &BulkFormat::Csv => "csv",
+            &BulkFormat::Yaml => "yaml",
+        }
+    }
+
+    /// Picks the format from a file name, e.g. `classes.csv` or `classes.yml`.
+    pub fn from_filename(name: &str) -> Result<BulkFormat, Error> {
+        let extension = name.rsplit('.').next().unwrap_or("").to_lowercase();
+        match extension.as_ref() {
+            "yml" => Ok(BulkFormat::Yaml),
+            other => BulkFormat::from_str(other).map_err(|_| {
+                Error::Parse(format!("unknown class file type: {} (csv | yaml)", name))
+            }),
+        }
+    }
+}
+
+impl FromStr for BulkFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<BulkFormat, Error> {
+        match s {
+            "csv" => Ok(BulkFormat::Csv),
+            "yaml" => Ok(BulkFormat::Yaml),
+            other => Err(Error::Parse(format!(
+                "invalid class file format: {} (csv | yaml)",
+                other
+            ))),
+        }
+    }
+}
+
+/// One class definition, with the same defaults as `add class`. Schedules are
+/// written as `mon-fri 08:00-16:00`, several of them separated by `;`.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct ClassRow {
+    name: String,
+    frequency: i32,
+    active: Option<bool>,
+    unique: Option<bool>,
+    visit: Option<i32>,
+    announce: Option<bool>,
+    schedule: Option<String>,
+}
+
+struct ClassDefinition {
+    name: String,
+    frequency: i32,
+    active: bool,
+    unique: bool,
+    visit: i32,
+    announce: bool,
+    schedules: Vec<(i32, i32, i32)>,
+}
+
+/// Adds every class in the document, or none of them. All rows are checked
+/// before anything is written and every problem is reported with its row.
+pub fn add_classes(
+    connection: &SqliteConnection,
+    world: i32,
+    format: BulkFormat,
+    document: &str,
+) -> Result<Vec<String>, Error> {
+    let rows = match format {
+        BulkFormat::Csv => csv_rows(document)?,
+        BulkFormat::Yaml => yaml_rows(document)?,
+    };
+    if rows.is_empty() {
+        return Err(Error::Invalid("no classes in the file".to_string()));
+    }
+
+    let tick = get_tick(connection, world)?;
+    let mut taken = get_classes(connection, world)?
+        .into_iter()
+        .map(|class| class.name)
+        .collect::<Vec<_>>();
+    let mut definitions = Vec::new();
+    let mut problems = Vec::new();
+    for (index, row) in rows.into_iter().enumerate() {
+        match row.and_then(|row| validate(row, &taken, &tick)) {
+            Ok(definition) => {
+                taken.push(definition.name.clone());
+                definitions.push(definition);
+            }
+            Err(e) => problems.push(format!("row {}: {}", index + 1, e)),
+        }
+    }
+    if !problems.is_empty() {
+        return Err(Error::Invalid(format!(
+            "no classes added, {} invalid rows:\n{}",
+            problems.len(),
+            problems.join("\n")
+        )));
+    }
+
+    let mut failure = None;
+    connection
+        .transaction::<_, diesel::result::Error, _>(|| {
+            insert_definitions(connection, world, &definitions).map_err(|e| {
+                failure = Some(e);
+                diesel::result::Error::RollbackTransaction
+            })
+        })
+        .map_err(|e| failure.unwrap_or_else(|| Error::database("could not add classes", e)))?;
+    Ok(definitions.into_iter().map(|d| d.name).collect())
+}
+
+fn csv_rows(document: &str) -> Result<Vec<Result<ClassRow, Error>>, Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(document.as_bytes());
+    reader
+        .headers()
+        .map_err(|e| Error::Parse(format!("invalid csv header: {}", e)))?;
+    Ok(reader
+        .deserialize()
+        .map(|row| row.map_err(|e| Error::Parse(e.to_string())))
+        .collect())
+}
+
+fn yaml_rows(document: &str) -> Result<Vec<Result<ClassRow, Error>>, Error> {
+    let values: Vec<serde_yaml::Value> = serde_yaml::from_str(document)
+        .map_err(|e| Error::Parse(format!("invalid yaml, expected a list of classes: {}", e)))?;
+    Ok(values
+        .into_iter()
+        .map(|value| serde_yaml::from_value(value).map_err(|e| Error::Parse(e.to_string())))
+        .collect())
+}
+
+fn validate(row: ClassRow, taken: &[String], tick: &Duration) -> Result<ClassDefinition, Error> {
+    let name = row.name.trim().to_string();
+    if name.is_empty() {
+        return Err(Error::Invalid("name is empty".to_string()));
+    }
+    if taken.contains(&name) {
+        return Err(Error::AlreadyExists(format!(
+            "class {} already exists",
+            name
+        )));
+    }
+    let visit = row.visit.unwrap_or(20);
+    if visit <= 0 {
+        return Err(Error::Invalid("visit time must be positive".to_string()));
+    }
+    check_rate(row.frequency, visit, tick)?;
+    let schedules = match row.schedule {
+        Some(ref raw) => raw
+            .split(';')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(parse_schedule)
+            .collect::<Result<Vec<_>, _>>()?,
+        None => Vec::new(),
+    };
+    Ok(ClassDefinition {
+        name,
+        frequency: row.frequency,
+        active: row.active.unwrap_or(true),
+        unique: row.unique.unwrap_or(false),
+        visit,
+        announce: row.announce.unwrap_or(false),
+        schedules,
+    })
+}
+
+fn parse_schedule(raw: &str) -> Result<(i32, i32, i32), Error> {
+    let mut parts = raw.split_whitespace();
+    let weekdays = parts
+        .next()
+        .ok_or_else(|| Error::Parse(format!("weekdays missing in schedule: {}", raw)))
+        .and_then(parse_weekdays)?;
+    let hours = parts
+        .next()
+        .ok_or_else(|| Error::Parse(format!("hours missing in schedule: {}", raw)))?;
+    let mut bounds = hours.splitn(2, '-');
+    let start = parse_time(bounds.next().unwrap_or(""))?;
+    let end = bounds
+        .next()
+        .ok_or_else(|| Error::Parse(format!("invalid hours (HH:MM-HH:MM): {}", hours)))
+        .and_then(parse_time)?;
+    if parts.next().is_some() {
+        return Err(Error::Parse(format!(
+            "invalid schedule (days HH:MM-HH:MM): {}",
+            raw
+        )));
+    }
+    Ok((weekdays, start, end))
+}
+
+fn insert_definitions(
+    connection: &SqliteConnection,
+    world: i32,
+    definitions: &[ClassDefinition],
+) -> Result<(), Error> {
+    for definition in definitions {
+        add_class(
+            connection,
+            world,
+            definition.name.clone(),
+            definition.frequency,
+            definition.active,
+            definition.unique,
+            definition.visit,
+        )?;
+        if definition.announce {
+            change_announce(connection, world, definition.name.clone(), true)?;
+        }
+        for &(weekdays, start, end) in &definition.schedules {
+            add_schedule(
+                connection,
+                world,
+                definition.name.clone(),
+                weekdays,
+                start,
+                end,
+            )?;
+        }
+    }
+    Ok(())
+}
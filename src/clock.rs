@@ -0,0 +1,256 @@
+use chrono::Duration;
+use chrono::NaiveDateTime;
+use chrono::Utc;
+use diesel::sqlite::SqliteConnection;
+use error::Error;
+use queries::get_constant;
+use queries::set_constant;
+use std::str::FromStr;
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f";
+
+/// Fastest supported clock rate; faster clocks would leave the date range within
+/// a human lifetime of wall time.
+pub const MAX_RATE: f64 = 1000f64;
+
+pub struct GameClock {
+    pub anchor: NaiveDateTime,
+    pub offset: Duration,
+    pub rate: f64,
+    pub paused: bool,
+}
+
+impl GameClock {
+    pub fn load(connection: &SqliteConnection, world: i32) -> Result<GameClock, Error> {
+        let anchor = match get_constant(connection, world, "clock_anchor".to_string())? {
+            None => NaiveDateTime::from_timestamp(0, 0),
+            Some(c) => NaiveDateTime::parse_from_str(&c.value, TIME_FORMAT)
+                .map_err(|e| Error::Invalid(format!("invalid clock_anchor constant: {}", e)))?,
+        };
+        let offset = match get_constant(connection, world, "clock_offset".to_string())? {
+            None => Duration::zero(),
+            Some(c) => i64::from_str(&c.value)
+                .map(Duration::milliseconds)
+                .map_err(|e| Error::Invalid(format!("invalid clock_offset constant: {}", e)))?,
+        };
+        let rate = match get_constant(connection, world, "clock_rate".to_string())? {
+            None => 1f64,
+            Some(c) => f64::from_str(&c.value)
+                .map_err(|e| Error::Invalid(format!("invalid clock_rate constant: {}", e)))?,
+        };
+        let paused = match get_constant(connection, world, "clock_paused".to_string())? {
+            None => false,
+            Some(c) => bool::from_str(&c.value)
+                .map_err(|e| Error::Invalid(format!("invalid clock_paused constant: {}", e)))?,
+        };
+        Ok(GameClock {
+            anchor,
+            offset,
+            rate,
+            paused,
+        })
+    }
+
+    pub fn save(&self, connection: &SqliteConnection, world: i32) -> Result<(), Error> {
+        set_constant(
+            connection,
+            world,
+            "clock_anchor".to_string(),
+            self.anchor.format(TIME_FORMAT).to_string(),
+        )?;
+        set_constant(
+            connection,
+            world,
+            "clock_offset".to_string(),
+            self.offset.num_milliseconds().to_string(),
+        )?;
+        set_constant(connection, world, "clock_rate".to_string(), self.rate.to_string())?;
+        set_constant(connection, world, "clock_paused".to_string(), self.paused.to_string())
+    }
+
+    pub fn at(&self, wall_time: &NaiveDateTime) -> Result<NaiveDateTime, Error> {
+        let elapsed = if self.paused {
+            Duration::zero()
+        } else {
+            let elapsed = (*wall_time - self.anchor).num_milliseconds() as f64 * self.rate;
+            Duration::milliseconds(elapsed as i64)
+        };
+        self.anchor
+            .checked_add_signed(self.offset)
+            .and_then(|time| time.checked_add_signed(elapsed))
+            .ok_or_else(|| Error::Invalid("the game time is out of range".to_string()))
+    }
+
+    pub fn now(&self) -> Result<NaiveDateTime, Error> {
+        self.at(&Utc::now().naive_utc())
+    }
+
+    pub fn change_rate(&mut self, wall_time: &NaiveDateTime, rate: f64) -> Result<(), Error> {
+        if !(rate >= 0f64) || rate > MAX_RATE {
+            return Err(Error::Invalid(format!(
+                "invalid clock rate: {} (must be from 0 to {})",
+                rate, MAX_RATE
+            )));
+        }
+        self.rebase(wall_time)?;
+        self.rate = rate;
+        Ok(())
+    }
+
+    pub fn pause(&mut self, wall_time: &NaiveDateTime) -> Result<(), Error> {
+        if self.paused {
+            return Err(Error::Invalid("the world is already paused".to_string()));
+        }
+        self.rebase(wall_time)?;
+        self.paused = true;
+        Ok(())
+    }
+
+    pub fn resume(&mut self, wall_time: &NaiveDateTime) -> Result<(), Error> {
+        if !self.paused {
+            return Err(Error::Invalid("the world is not paused".to_string()));
+        }
+        self.rebase(wall_time)?;
+        self.paused = false;
+        Ok(())
+    }
+
+    pub fn jump(&mut self, wall_time: &NaiveDateTime, shift: Duration) -> Result<(), Error> {
+        if shift < Duration::zero() {
+            return Err(Error::Invalid(
+                "the clock can only move forward".to_string(),
+            ));
+        }
+        self.rebase(wall_time)?;
+        let offset = self.offset.checked_add(&shift);
+        match offset {
+            Some(offset) if self.anchor.checked_add_signed(offset).is_some() => {
+                self.offset = offset;
+                Ok(())
+            }
+            _ => Err(Error::Invalid("the game time is out of range".to_string())),
+        }
+    }
+
+    fn rebase(&mut self, wall_time: &NaiveDateTime) -> Result<(), Error> {
+        let game_time = self.at(wall_time)?;
+        self.anchor = *wall_time;
+        self.offset = game_time - *wall_time;
+        Ok(())
+    }
+}
+
+pub fn now(connection: &SqliteConnection, world: i32) -> Result<NaiveDateTime, Error> {
+    GameClock::load(connection, world)?.now()
+}
+
+pub fn change_rate(connection: &SqliteConnection, world: i32, rate: f64) -> Result<(), Error> {
+    let mut clock = GameClock::load(connection, world)?;
+    clock.change_rate(&Utc::now().naive_utc(), rate)?;
+    clock.save(connection, world)
+}
+
+pub fn pause(connection: &SqliteConnection, world: i32) -> Result<(), Error> {
+    let mut clock = GameClock::load(connection, world)?;
+    clock.pause(&Utc::now().naive_utc())?;
+    clock.save(connection, world)
+}
+
+pub fn resume(connection: &SqliteConnection, world: i32) -> Result<(), Error> {
+    let mut clock = GameClock::load(connection, world)?;
+    clock.resume(&Utc::now().naive_utc())?;
+    clock.save(connection, world)
+}
+
+pub fn jump(connection: &SqliteConnection, world: i32, shift: Duration) -> Result<(), Error> {
+    let mut clock = GameClock::load(connection, world)?;
+    clock.jump(&Utc::now().naive_utc(), shift)?;
+    clock.save(connection, world)
+}
+
+pub fn change_time(
+    connection: &SqliteConnection,
+    world: i32,
+    time: NaiveDateTime,
+) -> Result<(), Error> {
+    let shift = time - now(connection, world)?;
+    jump(connection, world, shift)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn wall(minutes: i64) -> NaiveDateTime {
+        NaiveDate::from_ymd(2018, 9, 3).and_hms(12, 0, 0) + Duration::minutes(minutes)
+    }
+
+    fn game(minutes: i64) -> NaiveDateTime {
+        NaiveDate::from_ymd(1200, 1, 1).and_hms(0, 0, 0) + Duration::minutes(minutes)
+    }
+
+    fn clock() -> GameClock {
+        GameClock {
+            anchor: wall(0),
+            offset: game(0) - wall(0),
+            rate: 2f64,
+            paused: false,
+        }
+    }
+
+    #[test]
+    fn paused_time_stands_still_until_resumed() {
+        let mut clock = clock();
+        clock.pause(&wall(10)).unwrap();
+        assert_eq!(clock.at(&wall(10)).unwrap(), game(20));
+        assert_eq!(clock.at(&wall(50)).unwrap(), game(20));
+        assert!(clock.pause(&wall(50)).is_err());
+        clock.resume(&wall(50)).unwrap();
+        assert_eq!(clock.at(&wall(50)).unwrap(), game(20));
+        assert_eq!(clock.at(&wall(55)).unwrap(), game(30));
+        assert!(clock.resume(&wall(55)).is_err());
+    }
+
+    #[test]
+    fn rate_changes_keep_the_time_reached() {
+        let mut clock = clock();
+        clock.change_rate(&wall(10), 0.5).unwrap();
+        assert_eq!(clock.at(&wall(10)).unwrap(), game(20));
+        assert_eq!(clock.at(&wall(30)).unwrap(), game(30));
+        clock.change_rate(&wall(30), 0f64).unwrap();
+        assert_eq!(clock.at(&wall(90)).unwrap(), game(30));
+    }
+
+    #[test]
+    fn rates_are_limited() {
+        let mut clock = clock();
+        assert!(clock.change_rate(&wall(10), -1f64).is_err());
+        assert!(clock.change_rate(&wall(10), 1e12).is_err());
+        assert!(clock.change_rate(&wall(10), ::std::f64::NAN).is_err());
+        clock.change_rate(&wall(10), MAX_RATE).unwrap();
+        assert!(clock.at(&wall(10)).is_ok());
+    }
+
+    #[test]
+    fn jumps_only_go_forward_and_stay_in_range() {
+        let mut clock = clock();
+        clock.jump(&wall(10), Duration::minutes(60)).unwrap();
+        assert_eq!(clock.at(&wall(10)).unwrap(), game(80));
+        assert_eq!(clock.at(&wall(15)).unwrap(), game(90));
+        assert!(clock.jump(&wall(15), Duration::minutes(-1)).is_err());
+        assert!(clock.jump(&wall(15), Duration::days(1 << 30)).is_err());
+        assert_eq!(clock.at(&wall(15)).unwrap(), game(90));
+    }
+
+    #[test]
+    fn out_of_range_times_are_errors() {
+        let clock = GameClock {
+            anchor: wall(0),
+            offset: Duration::days(1 << 30),
+            rate: 1f64,
+            paused: false,
+        };
+        assert!(clock.at(&wall(0)).is_err());
+    }
+}
@@ -1,5 +1,21 @@
+use bulk::BulkFormat;
+use clock::MAX_RATE;
+use chrono::NaiveDate;
+use chrono::NaiveDateTime;
+use error::Error;
+use permissions::Grantee;
+use permissions::Level;
+use schedule::parse_time;
+use schedule::parse_weekdays;
+use settings::find_setting;
+use snapshot::ImportMode;
+use snapshot::SnapshotFormat;
 use std::collections::HashMap;
 use std::str::FromStr;
+use timing::MAX_TICK_SECONDS;
+
+/// Events listed by `show history` when no count is given.
+pub const DEFAULT_HISTORY: i64 = 10;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum Command {
@@ -7,27 +23,152 @@ pub enum Command {
     AddAliasCommand(String, Vec<String>),
     ShowInstances,
     ShowInstancesVerbose,
+    ShowChanges(NaiveDateTime),
+    ShowHistory(String, i64),
+    ShowStats(Option<NaiveDate>),
     ShowClasses,
-    AddClass(String, i32, bool, bool),
+    ShowSchedules(Option<String>),
+    ShowNames(String),
+    ShowClock,
+    ShowWorlds,
+    ShowGuild,
+    ShowPermissions,
+    ShowAliases,
+    ShowSettings,
+    AddClass(String, i32, bool, bool, i32),
+    AddClasses(BulkFormat, String),
+    AddSchedule(String, i32, i32, i32),
+    AddName(String, String),
+    AddSyllable(String, String),
+    AddWorld(String),
+    AddBinding(u64, String),
     RemoveInstances,
     RemoveInstance(i32),
     RemoveClass(String),
+    RemoveWorld(String),
+    RemoveBinding(u64),
+    RemoveSchedule(i32),
+    RemoveName(String, String),
+    RemoveSyllable(String, String),
     ChangeClassName(String, String),
     ChangeClassFreq(String, i32),
     ChangeClassActive(String, bool),
     ChangeClassUnique(String, bool),
+    ChangeClassVisit(String, i32),
+    ChangeClassAnnounce(String, bool),
+    ChangeClassArrival(String, Option<String>),
+    ChangeClassDeparture(String, Option<String>),
+    ChangeInstanceName(i32, Option<String>),
+    ChangeInstanceNote(i32, Option<String>),
+    ChangeInstanceAttribute(i32, String, Option<String>),
     FastForward(i32),
+    Pause,
+    Resume,
     ChangeStarter(String),
-    AddAdmin(u64),
-    RemoveAdmin(u64),
+    ChangeSetting(String, String),
+    ChangeTick(i64),
+    ChangeClockRate(f64),
+    ChangeClockTime(NaiveDateTime),
+    ChangeGuildPrefix(Option<String>),
+    ChangeGuildRole(Option<u64>),
+    ChangeGuildWorld(Option<String>),
+    ChangeGuildChannel(Option<u64>),
+    AddPermission(Grantee, Level),
+    RemovePermission(Grantee),
+    Export(SnapshotFormat, bool),
+    Import(ImportMode, String),
 }
 
 impl Command {
-    pub fn is_public(&self) -> bool {
+    pub fn required_level(&self) -> Level {
+        match self {
+            &Command::ShowInstances
+            | &Command::ShowInstancesVerbose
+            | &Command::ShowChanges(_)
+            | &Command::ShowHistory(_, _)
+            | &Command::ShowStats(_)
+            | &Command::ShowClock => Level::Viewer,
+            &Command::ShowClasses
+            | &Command::ShowSchedules(_)
+            | &Command::ShowNames(_)
+            | &Command::ShowWorlds
+            | &Command::ShowGuild
+            | &Command::ShowPermissions
+            | &Command::ShowAliases
+            | &Command::ShowSettings
+            | &Command::AddClass(..)
+            | &Command::AddClasses(..)
+            | &Command::AddSchedule(..)
+            | &Command::AddName(..)
+            | &Command::AddSyllable(..)
+            | &Command::AddAliasCommand(..)
+            | &Command::RemoveAliasCommand(_)
+            | &Command::RemoveInstances
+            | &Command::RemoveInstance(_)
+            | &Command::RemoveClass(_)
+            | &Command::RemoveSchedule(_)
+            | &Command::RemoveName(..)
+            | &Command::RemoveSyllable(..)
+            | &Command::ChangeClassName(..)
+            | &Command::ChangeClassFreq(..)
+            | &Command::ChangeClassActive(..)
+            | &Command::ChangeClassUnique(..)
+            | &Command::ChangeClassVisit(..)
+            | &Command::ChangeClassAnnounce(..)
+            | &Command::ChangeClassArrival(..)
+            | &Command::ChangeClassDeparture(..)
+            | &Command::ChangeInstanceName(..)
+            | &Command::ChangeInstanceNote(..)
+            | &Command::ChangeInstanceAttribute(..)
+            | &Command::FastForward(_)
+            | &Command::Pause
+            | &Command::Resume
+            | &Command::ChangeTick(_)
+            | &Command::ChangeClockRate(_)
+            | &Command::ChangeClockTime(_) => Level::Gm,
+            &Command::AddWorld(_)
+            | &Command::AddBinding(..)
+            | &Command::RemoveWorld(_)
+            | &Command::RemoveBinding(_)
+            | &Command::ChangeStarter(_)
+            | &Command::ChangeGuildPrefix(_)
+            | &Command::ChangeGuildRole(_)
+            | &Command::ChangeGuildWorld(_)
+            | &Command::ChangeGuildChannel(_)
+            | &Command::AddPermission(..)
+            | &Command::RemovePermission(_)
+            | &Command::Export(..)
+            | &Command::Import(..) => Level::Owner,
+            &Command::ChangeSetting(ref key, _) => {
+                find_setting(key).map_or(Level::Owner, |setting| setting.level)
+            }
+        }
+    }
+
+    /// Whether a level granted in a guild is enough: such grants cover the
+    /// guild and the world it uses, not other worlds or the global permissions
+    /// that snapshots carry.
+    pub fn within_world(&self, world: &str) -> bool {
+        match self {
+            &Command::AddWorld(_)
+            | &Command::RemoveWorld(_)
+            | &Command::Export(..)
+            | &Command::Import(..) => false,
+            &Command::AddBinding(_, ref name) | &Command::ChangeGuildWorld(Some(ref name)) => {
+                name == world
+            }
+            _ => true,
+        }
+    }
+
+    /// The channel the command points a world or the output at, which has to
+    /// be in the guild the command comes from.
+    pub fn target_channel(&self) -> Option<u64> {
         match self {
-            &Command::ShowInstances |
-            &Command::ShowInstancesVerbose => true,
-            _ => false
+            &Command::AddBinding(channel, _)
+            | &Command::RemoveBinding(channel)
+            | &Command::ChangeGuildChannel(Some(channel)) => Some(channel),
+            _ => None,
         }
     }
 }
@@ -35,7 +176,7 @@ impl Command {
 pub fn parse_aliased(
     mut command: Vec<String>,
     aliases: HashMap<String, Vec<String>>,
-) -> Result<Command, String> {
+) -> Result<Command, Error> {
     if let Some(pattern) = command.get(0).and_then(|c| aliases.get(c)) {
         command.reverse();
         command.pop();
@@ -44,13 +185,15 @@ pub fn parse_aliased(
             .map(|part| match part.as_ref() {
                 "?" => command
                     .pop()
-                    .ok_or_else(|| "alias requires more arguments!".to_string()),
+                    .ok_or_else(|| Error::Parse("alias requires more arguments!".to_string())),
                 fixed => Ok(fixed.to_owned()),
             })
             .collect::<Result<Vec<_>, _>>();
         let mut output = output?;
         if command.len() > 0 {
-            return Err("alias didn't use all the arguments!".to_string());
+            return Err(Error::Parse(
+                "alias didn't use all the arguments!".to_string(),
+            ));
         }
         parse_command(output)
     } else {
@@ -58,17 +201,23 @@ pub fn parse_aliased(
     }
 }
 
-pub fn parse_command(mut command: Vec<String>) -> Result<Command, String> {
+pub fn parse_command(mut command: Vec<String>) -> Result<Command, Error> {
     command.reverse();
 
     let command_group: String = command.pop().ok_or_else(|| {
-        "available commands: \
+        Error::Parse(
+            "available commands: \
          show | \
          add | \
          remove | \
          change | \
-         fast-forward [minutes: integer]"
-            .to_string()
+         fast-forward [minutes: integer] | \
+         pause | \
+         resume | \
+         export [format?: json|toml] [instances?] | \
+         import [mode: merge|replace] [snapshot]"
+                .to_string(),
+        )
     })?;
 
     match command_group.as_ref() {
@@ -77,20 +226,109 @@ pub fn parse_command(mut command: Vec<String>) -> Result<Command, String> {
         "remove" => parse_remove(command),
         "change" => parse_change(command),
         "fast-forward" => match command.pop().map(|s| i32::from_str(&s)) {
-            None => Err("missing minutes count".to_string()),
-            Some(Err(e)) => Err(format!("invalid minutes count format: {}", e.to_string())),
+            None => Err(Error::Parse("missing minutes count".to_string())),
+            Some(Err(e)) => Err(Error::Parse(format!(
+                "invalid minutes count format: {}",
+                e.to_string()
+            ))),
             Some(Ok(minutes)) => Ok(Command::FastForward(minutes)),
         },
-        arg => Err(format!("invalid command: {}", arg)),
+        "pause" => Ok(Command::Pause),
+        "resume" => Ok(Command::Resume),
+        "export" => {
+            let format = match command.pop() {
+                Some(format) => SnapshotFormat::from_str(&format)?,
+                None => SnapshotFormat::Json,
+            };
+            let instances = match command.pop().as_ref().map(String::as_ref) {
+                None => false,
+                Some("instances") => true,
+                Some(arg) => return Err(Error::Parse(format!("invalid command: {}", arg))),
+            };
+            Ok(Command::Export(format, instances))
+        }
+        "import" => {
+            let mode = command
+                .pop()
+                .ok_or_else(|| Error::Parse("missing import mode (merge | replace)".to_string()))
+                .and_then(|raw| ImportMode::from_str(&raw))?;
+            let document = command
+                .pop()
+                .ok_or_else(|| Error::Parse("missing snapshot".to_string()))?;
+            Ok(Command::Import(mode, document))
+        }
+        arg => Err(Error::Parse(format!("invalid command: {}", arg))),
+    }
+}
+
+fn parse_mention(raw: &str, prefix: &str, kind: &str) -> Result<u64, Error> {
+    let slice = if raw.starts_with(prefix) && raw.ends_with(">") {
+        &raw[prefix.len()..(raw.len() - 1)]
+    } else {
+        raw
+    };
+    u64::from_str(slice).map_err(|e| {
+        Error::Parse(format!(
+            "could not parse {} mention: {}",
+            kind,
+            e.to_string()
+        ))
+    })
+}
+
+fn parse_channel(raw: &str) -> Result<u64, Error> {
+    parse_mention(raw, "<#", "channel")
+}
+
+fn parse_role(raw: &str) -> Result<u64, Error> {
+    parse_mention(raw, "<@&", "role")
+}
+
+fn parse_datetime(date: &str, time: Option<String>) -> Result<NaiveDateTime, Error> {
+    let time = time.unwrap_or_else(|| "00:00".to_string());
+    NaiveDateTime::parse_from_str(&format!("{} {}", date, time), "%Y-%m-%d %H:%M")
+        .map_err(|e| Error::Parse(format!("invalid date (YYYY-MM-DD HH:MM) format: {}", e)))
+}
+
+fn parse_name_entry(mut command: Vec<String>, kind: &str) -> Result<(String, String), Error> {
+    let class = command
+        .pop()
+        .ok_or_else(|| Error::Parse(format!("class name missing")))?;
+    let value = command
+        .pop()
+        .ok_or_else(|| Error::Parse(format!("{} missing", kind)))?;
+    Ok((class, value))
+}
+
+fn parse_optional<T, F>(raw: String, parse: F) -> Result<Option<T>, Error>
+where
+    F: FnOnce(String) -> Result<T, Error>,
+{
+    match raw.as_ref() {
+        "none" => Ok(None),
+        _ => parse(raw).map(Some),
     }
 }
 
-pub fn parse_show(mut command: Vec<String>) -> Result<Command, String> {
+pub fn parse_show(mut command: Vec<String>) -> Result<Command, Error> {
     let target = command.pop().ok_or_else(|| {
-        "available commands: \n\
+        Error::Parse(
+            "available commands: \n\
          instance verbose? | \
-         class"
-            .to_string()
+         changes since [date: YYYY-MM-DD] [time?: HH:MM] | \
+         history [class] [count?] | \
+         stats [since?: YYYY-MM-DD] | \
+         class | \
+         schedule [class?] | \
+         names [class] | \
+         clock | \
+         world | \
+         guild | \
+         permission | \
+         alias | \
+         setting"
+                .to_string(),
+        )
     })?;
 
     match target.as_ref() {
@@ -99,21 +337,67 @@ pub fn parse_show(mut command: Vec<String>) -> Result<Command, String> {
             match modifier.as_ref().map(String::as_ref) {
                 None => Ok(Command::ShowInstances),
                 Some("verbose") => Ok(Command::ShowInstancesVerbose),
-                Some(arg) => Err(format!("invalid command: {}", arg)),
+                Some(arg) => Err(Error::Parse(format!("invalid command: {}", arg))),
+            }
+        }
+        "changes" => {
+            match command.pop().as_ref().map(String::as_ref) {
+                Some("since") => {}
+                _ => return Err(Error::Parse(format!("usage: changes since [date] [time?]"))),
             }
+            let date = command
+                .pop()
+                .ok_or_else(|| Error::Parse(format!("date missing")))?;
+            parse_datetime(&date, command.pop()).map(Command::ShowChanges)
+        }
+        "history" => {
+            let name = command
+                .pop()
+                .ok_or_else(|| Error::Parse(format!("class name missing")))?;
+            let count = match command.pop().map(|s| i64::from_str(&s)) {
+                None => DEFAULT_HISTORY,
+                Some(Ok(count)) if count > 0 => count,
+                _ => return Err(Error::Parse(format!("invalid count (positive integer)"))),
+            };
+            Ok(Command::ShowHistory(name, count))
         }
+        "stats" => match command.pop() {
+            None => Ok(Command::ShowStats(None)),
+            Some(date) => NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                .map(|date| Command::ShowStats(Some(date)))
+                .map_err(|e| Error::Parse(format!("invalid date (YYYY-MM-DD) format: {}", e))),
+        },
         "class" => Ok(Command::ShowClasses),
-        arg => Err(format!("invalid command arguments: {}", arg)),
+        "schedule" => Ok(Command::ShowSchedules(command.pop())),
+        "names" => {
+            let name = command
+                .pop()
+                .ok_or_else(|| Error::Parse(format!("class name missing")))?;
+            Ok(Command::ShowNames(name))
+        }
+        "clock" => Ok(Command::ShowClock),
+        "world" => Ok(Command::ShowWorlds),
+        "guild" => Ok(Command::ShowGuild),
+        "permission" => Ok(Command::ShowPermissions),
+        "alias" => Ok(Command::ShowAliases),
+        "setting" => Ok(Command::ShowSettings),
+        arg => Err(Error::Parse(format!("invalid command arguments: {}", arg))),
     }
 }
 
-pub fn parse_add(mut command: Vec<String>) -> Result<Command, String> {
+pub fn parse_add(mut command: Vec<String>) -> Result<Command, Error> {
     let target = command.pop().ok_or_else(|| {
-        "available commands:\n\
-         class [name] [freq: integer] [active?: true|false] [unique?: true|false]\n\
+        Error::Parse("available commands:\n\
+         class [name] [freq: integer] [active?: true|false] [unique?: true|false] [visit?: minutes]\n\
+         classes [format: csv|yaml] [file]\n\
+         schedule [class] [days: mon-fri|sat,sun|daily] [from: HH:MM] [to: HH:MM] (game time)\n\
+         name [class] [name]\n\
+         syllable [class] [syllable]\n\
          alias [alias] [command]\n\
-         admin [admin: mention]"
-            .to_string()
+         world [name]\n\
+         binding [channel: mention] [world]\n\
+         permission [level: viewer|gm|owner] [user or role: mention]"
+            .to_string())
     })?;
 
     match target.as_ref() {
@@ -122,51 +406,119 @@ pub fn parse_add(mut command: Vec<String>) -> Result<Command, String> {
             let frequency = command.pop().map(|s| i32::from_str(&s));
             let active = bool::from_str(&command.pop().unwrap_or_else(|| "true".to_string()));
             let unique = bool::from_str(&command.pop().unwrap_or_else(|| "false".to_string()));
-            match (name, frequency, active, unique) {
-                (None, _, _, _) => Err(format!("name unspecified")),
-                (_, None, _, _) => Err(format!("frequency unspecified")),
-                (_, Some(Err(_)), _, _) => Err(format!("invalid frequency (integer)")),
-                (_, _, Err(_), _) => Err(format!("invalid active/inactive state (true/false)")),
-                (_, _, _, Err(_)) => Err(format!("invalid unique state (true/false)")),
-                (Some(name), Some(Ok(frequency)), Ok(active), Ok(unique)) => {
-                    Ok(Command::AddClass(name, frequency, active, unique))
+            let visit = i32::from_str(&command.pop().unwrap_or_else(|| "20".to_string()));
+            match (name, frequency, active, unique, visit) {
+                (None, _, _, _, _) => Err(Error::Parse(format!("name unspecified"))),
+                (_, None, _, _, _) => Err(Error::Parse(format!("frequency unspecified"))),
+                (_, Some(Err(_)), _, _, _) => {
+                    Err(Error::Parse(format!("invalid frequency (integer)")))
+                }
+                (_, _, Err(_), _, _) => Err(Error::Parse(format!(
+                    "invalid active/inactive state (true/false)"
+                ))),
+                (_, _, _, Err(_), _) => {
+                    Err(Error::Parse(format!("invalid unique state (true/false)")))
+                }
+                (_, _, _, _, Err(_)) => Err(Error::Parse(format!(
+                    "invalid visit time (minutes: integer)"
+                ))),
+                (_, _, _, _, Ok(visit)) if visit <= 0 => {
+                    Err(Error::Parse(format!("visit time must be positive")))
+                }
+                (Some(name), Some(Ok(frequency)), Ok(active), Ok(unique), Ok(visit)) => {
+                    Ok(Command::AddClass(name, frequency, active, unique, visit))
                 }
             }
         }
+        "classes" => {
+            let format = command
+                .pop()
+                .ok_or_else(|| Error::Parse(format!("file format missing (csv | yaml)")))
+                .and_then(|raw| BulkFormat::from_str(&raw))?;
+            let document = command
+                .pop()
+                .ok_or_else(|| Error::Parse(format!("class file missing")))?;
+            Ok(Command::AddClasses(format, document))
+        }
+        "name" => parse_name_entry(command, "name").map(|(c, v)| Command::AddName(c, v)),
+        "syllable" => {
+            parse_name_entry(command, "syllable").map(|(c, v)| Command::AddSyllable(c, v))
+        }
+        "schedule" => {
+            let name = command
+                .pop()
+                .ok_or_else(|| Error::Parse(format!("class name missing")))?;
+            let weekdays = command
+                .pop()
+                .ok_or_else(|| Error::Parse(format!("weekdays missing")))
+                .and_then(|days| parse_weekdays(&days))?;
+            let start = command
+                .pop()
+                .ok_or_else(|| Error::Parse(format!("start time missing")))
+                .and_then(|time| parse_time(&time))?;
+            let end = command
+                .pop()
+                .ok_or_else(|| Error::Parse(format!("end time missing")))
+                .and_then(|time| parse_time(&time))?;
+            Ok(Command::AddSchedule(name, weekdays, start, end))
+        }
         "alias" => {
             let alias = command
                 .pop()
-                .ok_or_else(|| format!("missing alias string"))?;
+                .ok_or_else(|| Error::Parse(format!("missing alias string")))?;
             if command.len() == 0 {
-                return Err(format!("missing command string"));
+                return Err(Error::Parse(format!("missing command string")));
             }
             command.reverse();
             Ok(Command::AddAliasCommand(alias, command))
         }
-        "admin" => {
-            let raw_id = command
+        "world" => {
+            let name = command
+                .pop()
+                .ok_or_else(|| Error::Parse(format!("world name missing")))?;
+            Ok(Command::AddWorld(name))
+        }
+        "binding" => {
+            let channel = command
+                .pop()
+                .ok_or_else(|| Error::Parse(format!("missing channel (requires mention)")))
+                .and_then(|raw| parse_channel(&raw))?;
+            let name = command
                 .pop()
-                .ok_or_else(|| format!("missing admin (requires mention)"))?;
-            let slice = raw_id
-                .get(2..(raw_id.len() - 1))
-                .ok_or_else(|| format!("could not parse admin mention: {}", raw_id))?;
-            let id = u64::from_str(slice)
-                .map_err(|e| format!("could not parse admin mention: {}", e.to_string()))?;
-            Ok(Command::AddAdmin(id))
+                .ok_or_else(|| Error::Parse(format!("world name missing")))?;
+            Ok(Command::AddBinding(channel, name))
         }
-        arg => Err(format!("invalid command arguments: {}", arg)),
+        "permission" => {
+            let level = command
+                .pop()
+                .ok_or_else(|| Error::Parse(format!("missing level (viewer | gm | owner)")))
+                .and_then(|raw| Level::from_str(&raw))?;
+            let grantee = command
+                .pop()
+                .ok_or_else(|| Error::Parse(format!("missing user or role (requires mention)")))
+                .and_then(|raw| Grantee::from_str(&raw))?;
+            Ok(Command::AddPermission(grantee, level))
+        }
+        arg => Err(Error::Parse(format!("invalid command arguments: {}", arg))),
     }
 }
 
-pub fn parse_remove(mut command: Vec<String>) -> Result<Command, String> {
+pub fn parse_remove(mut command: Vec<String>) -> Result<Command, Error> {
     let target = command.pop().ok_or_else(|| {
-        "available commands:\n\
+        Error::Parse(
+            "available commands:\n\
          class [name]\n\
          instance [id: integer]\n\
          all_instances\n\
+         schedule [id: integer]\n\
+         name [class] [name]\n\
+         syllable [class] [syllable]\n\
          alias [alias]\n\
-         admin [admin: mention]"
-            .to_string()
+         world [name]\n\
+         binding [channel: mention]\n\
+         permission [user or role: mention]"
+                .to_string(),
+        )
     })?;
 
     match target.as_ref() {
@@ -174,88 +526,302 @@ pub fn parse_remove(mut command: Vec<String>) -> Result<Command, String> {
             let name = command.pop();
             match name {
                 Some(name) => Ok(Command::RemoveClass(name)),
-                None => Err(format!("name unspecified")),
+                None => Err(Error::Parse(format!("name unspecified"))),
             }
         }
         "instance" => {
-            let id_str = command.pop().ok_or_else(|| "id unspecified".to_string())?;
-            let id =
-                i32::from_str(&id_str).map_err(|e| format!("invalid id (integer) format: {}", e))?;
+            let id_str = command
+                .pop()
+                .ok_or_else(|| Error::Parse("id unspecified".to_string()))?;
+            let id = i32::from_str(&id_str)
+                .map_err(|e| Error::Parse(format!("invalid id (integer) format: {}", e)))?;
             Ok(Command::RemoveInstance(id))
         }
         "all_instances" => Ok(Command::RemoveInstances),
+        "name" => parse_name_entry(command, "name").map(|(c, v)| Command::RemoveName(c, v)),
+        "syllable" => {
+            parse_name_entry(command, "syllable").map(|(c, v)| Command::RemoveSyllable(c, v))
+        }
+        "schedule" => {
+            let id_str = command
+                .pop()
+                .ok_or_else(|| Error::Parse("id unspecified".to_string()))?;
+            let id = i32::from_str(&id_str)
+                .map_err(|e| Error::Parse(format!("invalid id (integer) format: {}", e)))?;
+            Ok(Command::RemoveSchedule(id))
+        }
         "alias" => {
             let alias = command
                 .pop()
-                .ok_or_else(|| format!("alias string missing"))?;
+                .ok_or_else(|| Error::Parse(format!("alias string missing")))?;
             Ok(Command::RemoveAliasCommand(alias))
         }
-        "admin" => {
-            let raw_id = command
+        "world" => {
+            let name = command
                 .pop()
-                .ok_or_else(|| format!("missing admin (requires mention)"))?;
-            let slice = raw_id
-                .get(2..(raw_id.len() - 1))
-                .ok_or_else(|| format!("could not parse admin mention: {}", raw_id))?;
-            let id = u64::from_str(slice)
-                .map_err(|e| format!("could not parse admin mention: {}", e.to_string()))?;
-            Ok(Command::RemoveAdmin(id))
+                .ok_or_else(|| Error::Parse(format!("world name missing")))?;
+            Ok(Command::RemoveWorld(name))
         }
-        arg => Err(format!("invalid command arguments: {}", arg)),
+        "binding" => {
+            let channel = command
+                .pop()
+                .ok_or_else(|| Error::Parse(format!("missing channel (requires mention)")))
+                .and_then(|raw| parse_channel(&raw))?;
+            Ok(Command::RemoveBinding(channel))
+        }
+        "permission" => {
+            let grantee = command
+                .pop()
+                .ok_or_else(|| Error::Parse(format!("missing user or role (requires mention)")))
+                .and_then(|raw| Grantee::from_str(&raw))?;
+            Ok(Command::RemovePermission(grantee))
+        }
+        arg => Err(Error::Parse(format!("invalid command arguments: {}", arg))),
     }
 }
 
-pub fn parse_change(mut command: Vec<String>) -> Result<Command, String> {
+pub fn parse_change(mut command: Vec<String>) -> Result<Command, Error> {
     let target = command.pop().ok_or_else(|| {
-        "available commands:\n\
+        Error::Parse(
+            "available commands:\n\
          class [old_name] name [name]\n\
          class [name] freq [freq: i32]\n\
          class [name] active [active: true|false]\n\
          class [name] unique [unique: true|false]\n\
-         starter [starter_string]"
-            .to_string()
+         class [name] visit [visit: minutes]\n\
+         class [name] announce [announce: true|false]\n\
+         class [name] arrival [message: text with {class}|none]\n\
+         class [name] departure [message: text with {class}|none]\n\
+         instance [id] name [name|none]\n\
+         instance [id] note [note|none]\n\
+         instance [id] attribute [key] [value|none]\n\
+         starter [starter_string]\n\
+         tick [seconds: integer]\n\
+         setting [key] [value]\n\
+         clock rate [multiplier: number]\n\
+         clock time [date: YYYY-MM-DD] [time: HH:MM] (starts out as UTC)\n\
+         guild prefix [prefix|none]\n\
+         guild role [admin role: mention|none]\n\
+         guild world [world|none]\n\
+         guild channel [output channel: mention|none]"
+                .to_string(),
+        )
     })?;
 
     match target.as_ref() {
         "class" => {
             let name = command
                 .pop()
-                .ok_or_else(|| "class name missing".to_string())?;
-            let key = command
-                .pop()
-                .ok_or_else(|| "available keys: name | freq | active | unique".to_string())?;
+                .ok_or_else(|| Error::Parse("class name missing".to_string()))?;
+            let key = command.pop().ok_or_else(|| {
+                Error::Parse(
+                    "available keys: name | freq | active | unique | visit | announce | arrival \
+                     | departure"
+                        .to_string(),
+                )
+            })?;
             let value = command
                 .pop()
-                .ok_or_else(|| "new value missing".to_string())?;
+                .ok_or_else(|| Error::Parse("new value missing".to_string()))?;
             match key.as_ref() {
                 "name" => Ok(Command::ChangeClassName(name, value)),
                 "freq" => {
                     let freq = i32::from_str(&value).map_err(|e| {
-                        format!("invalid frequency (integer) format: {}", e.to_string())
+                        Error::Parse(format!(
+                            "invalid frequency (integer) format: {}",
+                            e.to_string()
+                        ))
                     })?;
                     Ok(Command::ChangeClassFreq(name, freq))
                 }
                 "active" => {
                     let active = bool::from_str(&value).map_err(|e| {
-                        format!("invalid active (boolean) format: {}", e.to_string())
+                        Error::Parse(format!(
+                            "invalid active (boolean) format: {}",
+                            e.to_string()
+                        ))
                     })?;
                     Ok(Command::ChangeClassActive(name, active))
                 }
                 "unique" => {
                     let unique = bool::from_str(&value).map_err(|e| {
-                        format!("invalid unique (boolean) format: {}", e.to_string())
+                        Error::Parse(format!(
+                            "invalid unique (boolean) format: {}",
+                            e.to_string()
+                        ))
                     })?;
                     Ok(Command::ChangeClassUnique(name, unique))
                 }
-                arg => Err(format!("invalid key: {}", arg)),
+                "visit" => {
+                    let visit = i32::from_str(&value).map_err(|e| {
+                        Error::Parse(format!(
+                            "invalid visit time (minutes: integer) format: {}",
+                            e.to_string()
+                        ))
+                    })?;
+                    if visit <= 0 {
+                        return Err(Error::Parse(format!("visit time must be positive")));
+                    }
+                    Ok(Command::ChangeClassVisit(name, visit))
+                }
+                "announce" => {
+                    let announce = bool::from_str(&value).map_err(|e| {
+                        Error::Parse(format!(
+                            "invalid announce (boolean) format: {}",
+                            e.to_string()
+                        ))
+                    })?;
+                    Ok(Command::ChangeClassAnnounce(name, announce))
+                }
+                "arrival" => Ok(Command::ChangeClassArrival(
+                    name,
+                    parse_optional(value, Ok)?,
+                )),
+                "departure" => Ok(Command::ChangeClassDeparture(
+                    name,
+                    parse_optional(value, Ok)?,
+                )),
+                arg => Err(Error::Parse(format!("invalid key: {}", arg))),
+            }
+        }
+        "instance" => {
+            let id = command
+                .pop()
+                .ok_or_else(|| Error::Parse("instance id missing".to_string()))?;
+            let id = i32::from_str(&id).map_err(|e| {
+                Error::Parse(format!("invalid instance id (integer) format: {}", e.to_string()))
+            })?;
+            let key = command.pop().ok_or_else(|| {
+                Error::Parse("available keys: name | note | attribute".to_string())
+            })?;
+            match key.as_ref() {
+                "name" => {
+                    let value = command
+                        .pop()
+                        .ok_or_else(|| Error::Parse("new name missing".to_string()))?;
+                    Ok(Command::ChangeInstanceName(id, parse_optional(value, Ok)?))
+                }
+                "note" => {
+                    let value = command
+                        .pop()
+                        .ok_or_else(|| Error::Parse("new note missing".to_string()))?;
+                    Ok(Command::ChangeInstanceNote(id, parse_optional(value, Ok)?))
+                }
+                "attribute" => {
+                    let name = command
+                        .pop()
+                        .ok_or_else(|| Error::Parse("attribute name missing".to_string()))?;
+                    let value = command
+                        .pop()
+                        .ok_or_else(|| Error::Parse("new value missing".to_string()))?;
+                    Ok(Command::ChangeInstanceAttribute(
+                        id,
+                        name,
+                        parse_optional(value, Ok)?,
+                    ))
+                }
+                arg => Err(Error::Parse(format!("invalid key: {}", arg))),
             }
         }
         "starter" => {
             let starter = command
                 .pop()
-                .ok_or_else(|| format!("new starter string missing"))?;
+                .ok_or_else(|| Error::Parse(format!("new starter string missing")))?;
             Ok(Command::ChangeStarter(starter))
         }
-        arg => Err(format!("invalid command arguments: {}", arg)),
+        "setting" => {
+            let key = command
+                .pop()
+                .ok_or_else(|| Error::Parse(format!("setting name missing")))?;
+            find_setting(&key)?;
+            let value = command
+                .pop()
+                .ok_or_else(|| Error::Parse(format!("setting value missing")))?;
+            Ok(Command::ChangeSetting(key, value))
+        }
+        "tick" => {
+            let seconds = command
+                .pop()
+                .ok_or_else(|| Error::Parse(format!("tick length missing")))?;
+            let seconds = i64::from_str(&seconds).map_err(|e| {
+                Error::Parse(format!(
+                    "invalid tick length (seconds) format: {}",
+                    e.to_string()
+                ))
+            })?;
+            if seconds <= 0 || seconds > MAX_TICK_SECONDS {
+                return Err(Error::Parse(format!(
+                    "tick length must be from 1 to {} seconds",
+                    MAX_TICK_SECONDS
+                )));
+            }
+            Ok(Command::ChangeTick(seconds))
+        }
+        "clock" => {
+            let key = command
+                .pop()
+                .ok_or_else(|| Error::Parse("available keys: rate | time".to_string()))?;
+            match key.as_ref() {
+                "rate" => {
+                    let rate = command
+                        .pop()
+                        .ok_or_else(|| Error::Parse(format!("clock rate missing")))?;
+                    let rate = f64::from_str(&rate).map_err(|e| {
+                        Error::Parse(format!("invalid clock rate (number) format: {}", e))
+                    })?;
+                    if !(rate >= 0f64) || rate > MAX_RATE {
+                        return Err(Error::Parse(format!(
+                            "clock rate must be a number from 0 to {}",
+                            MAX_RATE
+                        )));
+                    }
+                    Ok(Command::ChangeClockRate(rate))
+                }
+                "time" => {
+                    let date = command
+                        .pop()
+                        .ok_or_else(|| Error::Parse(format!("date missing")))?;
+                    parse_datetime(&date, command.pop()).map(Command::ChangeClockTime)
+                }
+                arg => Err(Error::Parse(format!("invalid key: {}", arg))),
+            }
+        }
+        "guild" => {
+            let key = command.pop().ok_or_else(|| {
+                Error::Parse("available keys: prefix | role | world | channel".to_string())
+            })?;
+            let value = command
+                .pop()
+                .ok_or_else(|| Error::Parse("new value missing".to_string()))?;
+            match key.as_ref() {
+                "prefix" => Ok(Command::ChangeGuildPrefix(parse_optional(value, Ok)?)),
+                "role" => Ok(Command::ChangeGuildRole(parse_optional(value, |v| {
+                    parse_role(&v)
+                })?)),
+                "world" => Ok(Command::ChangeGuildWorld(parse_optional(value, Ok)?)),
+                "channel" => Ok(Command::ChangeGuildChannel(parse_optional(value, |v| {
+                    parse_channel(&v)
+                })?)),
+                arg => Err(Error::Parse(format!("invalid key: {}", arg))),
+            }
+        }
+        arg => Err(Error::Parse(format!("invalid command arguments: {}", arg))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guild_grants_stay_in_their_world() {
+        assert!(Command::AddBinding(1, "north".to_string()).within_world("north"));
+        assert!(!Command::AddBinding(1, "south".to_string()).within_world("north"));
+        assert!(!Command::ChangeGuildWorld(Some("south".to_string())).within_world("north"));
+        assert!(Command::ChangeGuildWorld(None).within_world("north"));
+        assert!(!Command::RemoveWorld("north".to_string()).within_world("north"));
+        assert!(Command::RemoveBinding(1).within_world("north"));
+        assert_eq!(Command::RemoveBinding(1).target_channel(), Some(1));
+        assert_eq!(Command::ChangeGuildChannel(None).target_channel(), None);
     }
 }
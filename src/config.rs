@@ -0,0 +1,93 @@
+use error::Error;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use toml;
+
+pub const DEFAULT_CONFIG_PATH: &str = "habot.toml";
+pub const DEFAULT_PREFIX: &str = "!";
+pub const DEFAULT_ANNOUNCE_SECONDS: u64 = 60;
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Config {
+    pub token: Option<String>,
+    pub database_url: Option<String>,
+    pub prefix: Option<String>,
+    pub announce_seconds: Option<u64>,
+}
+
+impl Config {
+    // an explicitly given path must exist, the default habot.toml is optional
+    // and HABOT_* environment variables override either
+    pub fn load(path: Option<&str>) -> Result<Config, Error> {
+        let path = path
+            .map(str::to_owned)
+            .or_else(|| env::var("HABOT_CONFIG").ok());
+        let mut config = match path {
+            Some(path) => Config::from_file(&path)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                Config::from_file(DEFAULT_CONFIG_PATH)?
+            }
+            None => Config::default(),
+        };
+        config.override_from_env();
+        Ok(config)
+    }
+
+    pub fn from_file(path: &str) -> Result<Config, Error> {
+        let mut content = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut content))
+            .map_err(|e| Error::Config(format!("could not read config file {}: {}", path, e)))?;
+        toml::from_str(&content)
+            .map_err(|e| Error::Config(format!("invalid config file {}: {}", path, e)))
+    }
+
+    fn override_from_env(&mut self) {
+        if let Ok(token) = env::var("HABOT_TOKEN") {
+            self.token = Some(token);
+        }
+        if let Ok(url) = env::var("HABOT_DATABASE_URL") {
+            self.database_url = Some(url);
+        }
+        if let Ok(prefix) = env::var("HABOT_PREFIX") {
+            self.prefix = Some(prefix);
+        }
+        if let Some(seconds) = env::var("HABOT_ANNOUNCE_SECONDS")
+            .ok()
+            .and_then(|s| u64::from_str(&s).ok())
+        {
+            self.announce_seconds = Some(seconds);
+        }
+    }
+
+    pub fn token(&self) -> Result<&str, Error> {
+        self.token.as_ref().map(String::as_ref).ok_or_else(|| {
+            Error::Config(
+                "missing bot token: set `token` in the config file or HABOT_TOKEN".to_string(),
+            )
+        })
+    }
+
+    pub fn database_url(&self) -> Result<&str, Error> {
+        self.database_url
+            .as_ref()
+            .map(String::as_ref)
+            .ok_or_else(|| {
+                Error::Config("missing database url: set `database_url` in the config file or HABOT_DATABASE_URL"
+                    .to_string())
+            })
+    }
+
+    pub fn prefix(&self) -> &str {
+        self.prefix.as_ref().map_or(DEFAULT_PREFIX, String::as_ref)
+    }
+
+    // how often the bot updates npcs to post arrivals and departures
+    pub fn announce_interval(&self) -> Duration {
+        Duration::from_secs(self.announce_seconds.unwrap_or(DEFAULT_ANNOUNCE_SECONDS))
+    }
+}
@@ -0,0 +1,91 @@
+use diesel::result::DatabaseErrorKind;
+use diesel::result::Error as DieselError;
+use std::error;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    NotFound(String),
+    AlreadyExists(String),
+    Parse(String),
+    Invalid(String),
+    Permission(String),
+    SchemaViolation(String),
+    Locked(String),
+    Database(String),
+    Connection(String),
+    Config(String),
+}
+
+impl Error {
+    /// Classifies a diesel error, prefixing its message with what was being done.
+    pub fn database<C: Into<String>>(context: C, error: DieselError) -> Error {
+        let message = format!("{}: {}", context.into(), error);
+        match error {
+            DieselError::NotFound => Error::NotFound(message),
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                Error::AlreadyExists(message)
+            }
+            DieselError::DatabaseError(_, ref info) if is_locked(info.message()) => {
+                Error::Locked(message)
+            }
+            _ => Error::Database(message),
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            &Error::Parse(_) | &Error::Invalid(_) => 2,
+            &Error::NotFound(_) => 3,
+            &Error::AlreadyExists(_) => 4,
+            &Error::Permission(_) => 5,
+            &Error::Config(_) => 6,
+            &Error::Locked(_) => 7,
+            &Error::Database(_) | &Error::SchemaViolation(_) | &Error::Connection(_) => 8,
+        }
+    }
+}
+
+fn is_locked(message: &str) -> bool {
+    message.contains("database is locked") || message.contains("database table is locked")
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Error::SchemaViolation(ref message) => write!(f, "schema violation? {}", message),
+            &Error::NotFound(ref message)
+            | &Error::AlreadyExists(ref message)
+            | &Error::Parse(ref message)
+            | &Error::Invalid(ref message)
+            | &Error::Permission(ref message)
+            | &Error::Locked(ref message)
+            | &Error::Database(ref message)
+            | &Error::Connection(ref message)
+            | &Error::Config(ref message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match self {
+            &Error::NotFound(_) => "not found",
+            &Error::AlreadyExists(_) => "already exists",
+            &Error::Parse(_) => "parse error",
+            &Error::Invalid(_) => "invalid value",
+            &Error::Permission(_) => "permission denied",
+            &Error::SchemaViolation(_) => "schema violation",
+            &Error::Locked(_) => "database locked",
+            &Error::Database(_) => "database error",
+            &Error::Connection(_) => "connection error",
+            &Error::Config(_) => "configuration error",
+        }
+    }
+}
+
+impl From<DieselError> for Error {
+    fn from(error: DieselError) -> Error {
+        Error::database("database error", error)
+    }
+}
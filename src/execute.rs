@@ -1,130 +1,540 @@
+use bulk::add_classes;
 use chrono;
-use chrono::Local;
-use chrono::TimeZone;
+use chrono::Datelike;
+use clock::change_rate;
+use clock::change_time;
+use clock::pause;
+use clock::resume;
+use clock::GameClock;
 use command::Command;
 use diesel;
 use diesel::sqlite::SqliteConnection;
-use diesel::RunQueryDsl;
-use queries::add_admin;
+use error::Error;
+use models::NpcClass;
+use models::NpcEvent;
+use models::World;
+use output::AliasEntry;
+use output::CommandOutput;
+use output::GuildEntry;
+use output::InstanceCount;
+use output::InstanceEntry;
+use output::ScheduleEntry;
+use output::SettingEntry;
+use output::StatEntry;
+use output::WorldEntry;
 use queries::add_alias;
 use queries::add_class;
+use queries::add_name;
+use queries::add_schedule;
+use queries::add_world;
+use queries::applies_in;
+use queries::bind_world;
 use queries::change_active;
-use queries::change_constant;
+use queries::change_announce;
+use queries::change_arrival;
+use queries::change_departure;
 use queries::change_freq;
+use queries::change_guild_channel;
+use queries::change_guild_prefix;
+use queries::change_guild_role;
+use queries::change_guild_world;
+use queries::change_instance_attribute;
+use queries::change_instance_name;
+use queries::change_instance_note;
 use queries::change_name;
+use queries::change_unique;
+use queries::change_visit;
+use queries::get_aliases;
+use queries::get_bindings;
 use queries::get_classes;
+use queries::get_class;
+use queries::get_class_events;
+use queries::get_events;
+use queries::get_guild_settings;
 use queries::get_instances;
-use queries::remove_admin;
+use queries::get_names;
+use queries::get_permissions;
+use queries::get_schedules;
+use queries::get_worlds;
+use queries::grant_permission;
 use queries::remove_alias;
 use queries::remove_class;
-use queries::remove_instance;
-use schema::npc_instances;
+use queries::remove_name;
+use queries::remove_schedule;
+use queries::remove_world;
+use queries::revoke_permission;
+use queries::unbind_world;
+use settings::change_setting;
+use settings::get_settings;
+use snapshot::export_world;
+use snapshot::from_document;
+use snapshot::import_world;
+use snapshot::to_document;
 use std::collections::HashMap;
+use timing::arrives;
+use timing::change_tick;
 use timing::fast_forward_instances;
+use timing::remove_instances;
 use timing::update_instances;
-use queries::change_unique;
 
-pub fn execute_command(connection: &SqliteConnection, command: Command) -> Result<String, String> {
+pub struct Scope {
+    pub world: World,
+    pub guild: Option<u64>,
+}
+
+impl Scope {
+    fn guild(&self) -> Result<u64, Error> {
+        self.guild.ok_or_else(|| {
+            Error::Invalid("guild settings are only available inside a guild".to_string())
+        })
+    }
+}
+
+/// Runs the command in a single transaction, so a failure halfway through
+/// (e.g. while spawning instances for several classes) leaves no partial changes.
+/// The write lock is taken up front, a deferred transaction could not wait
+/// for it when upgrading from a read.
+pub fn execute_command(
+    connection: &SqliteConnection,
+    scope: &Scope,
+    command: Command,
+) -> Result<CommandOutput, Error> {
+    let mut failure = None;
+    connection
+        .immediate_transaction::<_, diesel::result::Error, _>(|| {
+            run_command(connection, scope, command).map_err(|e| {
+                failure = Some(e);
+                diesel::result::Error::RollbackTransaction
+            })
+        })
+        .map_err(|e| failure.unwrap_or_else(|| Error::database("could not execute command", e)))
+}
+
+/// Counts the arrivals of a class, so the collapsed visits of a unique npc
+/// are one visit, and finds its latest arrival and departure.
+fn class_stats(class: NpcClass, events: &[NpcEvent]) -> StatEntry {
+    let id = class.id;
+    let mut entry = StatEntry {
+        class_name: class.name,
+        visits: 0,
+        last_arrival: None,
+        last_departure: None,
+    };
+    // events are ordered by time, so the last match is the latest
+    for event in events.iter().filter(|e| e.class == id) {
+        match event.kind.as_ref() {
+            "spawned" if arrives(events, event) => {
+                entry.visits += 1;
+                entry.last_arrival = Some(event.time);
+            }
+            "expired" | "removed" => entry.last_departure = Some(event.time),
+            _ => {}
+        }
+    }
+    entry
+}
+
+fn run_command(
+    connection: &SqliteConnection,
+    scope: &Scope,
+    command: Command,
+) -> Result<CommandOutput, Error> {
+    let world = scope.world.id;
     match command {
         Command::ShowInstances => {
-            update_instances(connection)?;
+            update_instances(connection, world)?;
             let mut counts: HashMap<String, usize> = HashMap::new();
-            get_instances(connection)?
+            get_instances(connection, world)?
                 .into_iter()
                 .for_each(|(_instance, class)| {
                     let count = counts.entry(class.name).or_insert(0);
                     *count += 1;
                 });
-            let mut result = counts.into_iter().collect::<Vec<_>>();
-            result.sort();
-            let result = result
+            let mut result = counts
                 .into_iter()
-                .map(|(name, count)| format!("{}: {}", name, count))
-                .collect::<Vec<String>>();
-            Ok(if result.len() == 0 {
-                "no npcs!".to_string()
-            } else {
-                result.join("\n")
-            })
+                .map(|(class_name, count)| InstanceCount { class_name, count })
+                .collect::<Vec<_>>();
+            result.sort_by(|a, b| a.class_name.cmp(&b.class_name));
+            Ok(CommandOutput::InstanceSummary(result))
         }
         Command::ShowInstancesVerbose => {
-            update_instances(connection)?;
-            let mut result = get_instances(connection)?
+            update_instances(connection, world)?;
+            let mut result = get_instances(connection, world)?
                 .into_iter()
-                .map(|(instance, class)| {
-                    format!(
-                        "id: {}, {}, active until {}",
-                        instance.id,
-                        class.name,
-                        Local
-                            .from_utc_datetime(&instance.active_until)
-                            .time()
-                            .format("%H:%M:%S"),
-                    )
+                .map(|(instance, class)| InstanceEntry {
+                    instance,
+                    class_name: class.name,
                 })
-                .collect::<Vec<String>>();
-            result.sort();
-            Ok(if result.len() == 0 {
-                "no npcs!".to_string()
-            } else {
-                result.join("\n")
-            })
+                .collect::<Vec<_>>();
+            result.sort_by_key(|entry| entry.instance.id);
+            Ok(CommandOutput::InstanceList(result))
+        }
+        Command::ShowChanges(since) => {
+            update_instances(connection, world)?;
+            get_events(connection, world, since).map(CommandOutput::EventList)
+        }
+        Command::ShowHistory(name, count) => {
+            update_instances(connection, world)?;
+            let class = get_class(connection, world, name)?;
+            get_class_events(connection, world, class.id, count).map(CommandOutput::EventList)
+        }
+        Command::ShowStats(since) => {
+            update_instances(connection, world)?;
+            let since = match since {
+                Some(date) => date.and_hms(0, 0, 0),
+                None => {
+                    let now = GameClock::load(connection, world)?.now()?.date();
+                    now.with_day(1).unwrap_or(now).and_hms(0, 0, 0)
+                }
+            };
+            let events = get_events(connection, world, since)?;
+            let mut stats = get_classes(connection, world)?
+                .into_iter()
+                .map(|class| class_stats(class, &events))
+                .collect::<Vec<_>>();
+            stats.sort_by(|a, b| a.class_name.cmp(&b.class_name));
+            Ok(CommandOutput::StatList { since, stats })
         }
         Command::ShowClasses => {
-            let mut result = get_classes(connection)?;
+            let mut result = get_classes(connection, world)?;
             result.sort();
-            let result = result
+            Ok(CommandOutput::ClassList(result))
+        }
+        Command::ShowNames(class) => {
+            get_names(connection, world, class).map(CommandOutput::NameList)
+        }
+        Command::ShowSchedules(name) => {
+            let mut result = get_schedules(connection, world)?
                 .into_iter()
-                .map(|i| {
-                    format!(
-                        "{}, frequency: {}, active: {}",
-                        i.name,
-                        i.commonality,
-                        if i.active > 0 { true } else { false }
-                    )
+                .filter(|(_schedule, class)| name.as_ref().map_or(true, |n| n == &class.name))
+                .map(|(schedule, class)| ScheduleEntry {
+                    schedule,
+                    class_name: class.name,
                 })
-                .collect::<Vec<String>>();
-            Ok(if result.len() == 0 {
-                "no npc classes!".to_string()
-            } else {
-                result.join("\n")
+                .collect::<Vec<_>>();
+            result.sort_by_key(|entry| entry.schedule.id);
+            Ok(CommandOutput::ScheduleList(result))
+        }
+        Command::ShowClock => {
+            let clock = GameClock::load(connection, world)?;
+            Ok(CommandOutput::Clock {
+                time: clock.now()?,
+                rate: clock.rate,
+                paused: clock.paused,
             })
         }
-        Command::AddClass(name, freq, active, unique) => {
-            add_class(connection, name, freq, active, unique).map(|()| "ok".to_string())
+        Command::ShowWorlds => {
+            let bindings = get_bindings(connection)?;
+            let mut result = get_worlds(connection)?
+                .into_iter()
+                .map(|w| WorldEntry {
+                    channels: bindings
+                        .iter()
+                        .filter(|(_binding, bound)| bound.id == w.id)
+                        .map(|(binding, _bound)| binding.channel_id.clone())
+                        .collect(),
+                    current: w.id == world,
+                    name: w.name,
+                })
+                .collect::<Vec<_>>();
+            result.sort_by(|a, b| a.name.cmp(&b.name));
+            Ok(CommandOutput::WorldList(result))
+        }
+        Command::ShowGuild => {
+            let settings = get_guild_settings(connection, scope.guild()?)?;
+            let default = settings.as_ref().and_then(|s| s.world);
+            Ok(CommandOutput::Guild(GuildEntry {
+                prefix: settings.as_ref().and_then(|s| s.prefix.clone()),
+                admin_role: settings.as_ref().and_then(|s| s.admin_role.clone()),
+                world: get_worlds(connection)?
+                    .into_iter()
+                    .find(|w| Some(w.id) == default)
+                    .map(|w| w.name),
+                output_channel: settings.as_ref().and_then(|s| s.output_channel.clone()),
+            }))
+        }
+        Command::ShowPermissions => {
+            let mut result = get_permissions(connection)?;
+            if scope.guild.is_some() {
+                result.retain(|p| applies_in(p, scope.guild));
+            }
+            result.sort_by(|a, b| (&a.kind, &a.subject).cmp(&(&b.kind, &b.subject)));
+            Ok(CommandOutput::PermissionList(result))
+        }
+        Command::ShowAliases => {
+            let mut result = get_aliases(connection, world)?
+                .into_iter()
+                .map(|(alias, command)| AliasEntry { alias, command })
+                .collect::<Vec<_>>();
+            result.sort_by(|a, b| a.alias.cmp(&b.alias));
+            Ok(CommandOutput::AliasList(result))
+        }
+        Command::ShowSettings => {
+            let mut result = get_settings(connection, world)?
+                .into_iter()
+                .map(|(setting, value)| SettingEntry {
+                    key: setting.key.to_string(),
+                    value,
+                    default: setting.default(),
+                    description: setting.description.to_string(),
+                })
+                .collect::<Vec<_>>();
+            result.sort_by(|a, b| a.key.cmp(&b.key));
+            Ok(CommandOutput::SettingList(result))
+        }
+        Command::AddClass(name, freq, active, unique, visit) => {
+            add_class(connection, world, name, freq, active, unique, visit)
+                .map(|()| CommandOutput::Ok)
+        }
+        Command::AddClasses(format, document) => {
+            add_classes(connection, world, format, &document).map(CommandOutput::ClassesAdded)
+        }
+        Command::AddSchedule(name, weekdays, start, end) => {
+            add_schedule(connection, world, name, weekdays, start, end).map(|()| CommandOutput::Ok)
+        }
+        Command::AddName(class, name) => {
+            add_name(connection, world, class, "name", name).map(|()| CommandOutput::Ok)
+        }
+        Command::AddSyllable(class, syllable) => {
+            add_name(connection, world, class, "syllable", syllable).map(|()| CommandOutput::Ok)
         }
         Command::AddAliasCommand(alias, cmd) => {
-            add_alias(connection, cmd, alias).map(|()| "ok".to_string())
-        }
-        Command::AddAdmin(uid) => add_admin(connection, uid).map(|()| "ok".to_string()),
-        Command::RemoveInstances => diesel::delete(npc_instances::table)
-            .execute(connection)
-            .map(|c| format!("deleted instances: {}", c))
-            .map_err(|e| format!("could not remove instances: {}", e)),
-        Command::RemoveClass(name) => remove_class(connection, name).map(|()| "ok".to_string()),
-        Command::RemoveInstance(id) => remove_instance(connection, id).map(|()| "ok".to_string()),
+            add_alias(connection, world, cmd, alias).map(|()| CommandOutput::Ok)
+        }
+        Command::AddWorld(name) => add_world(connection, name).map(|()| CommandOutput::Ok),
+        Command::AddBinding(channel, name) => {
+            bind_world(connection, channel, name).map(|()| CommandOutput::Ok)
+        }
+        Command::AddPermission(grantee, level) => {
+            grant_permission(connection, scope.guild, grantee, level).map(|()| CommandOutput::Ok)
+        }
+        Command::RemoveInstances => {
+            remove_instances(connection, world, None).map(CommandOutput::Deleted)
+        }
+        Command::RemoveClass(name) => {
+            remove_class(connection, world, name).map(|()| CommandOutput::Ok)
+        }
+        Command::RemoveInstance(id) => {
+            remove_instances(connection, world, Some(id)).map(|_| CommandOutput::Ok)
+        }
+        Command::RemoveSchedule(id) => {
+            remove_schedule(connection, world, id).map(|()| CommandOutput::Ok)
+        }
+        Command::RemoveName(class, name) => {
+            remove_name(connection, world, class, "name", name).map(|()| CommandOutput::Ok)
+        }
+        Command::RemoveSyllable(class, syllable) => {
+            remove_name(connection, world, class, "syllable", syllable).map(|()| CommandOutput::Ok)
+        }
         Command::RemoveAliasCommand(alias) => {
-            remove_alias(connection, alias).map(|()| "ok".to_string())
+            remove_alias(connection, world, alias).map(|()| CommandOutput::Ok)
+        }
+        Command::RemoveWorld(name) => remove_world(connection, name).map(|()| CommandOutput::Ok),
+        Command::RemoveBinding(channel) => {
+            unbind_world(connection, channel).map(|()| CommandOutput::Ok)
+        }
+        Command::RemovePermission(grantee) => {
+            revoke_permission(connection, scope.guild, grantee).map(|()| CommandOutput::Ok)
         }
-        Command::RemoveAdmin(uid) => remove_admin(connection, uid).map(|()| "ok".to_string()),
         Command::ChangeClassName(old, new) => {
-            change_name(connection, old, new).map(|()| "ok".to_string())
+            change_name(connection, world, old, new).map(|()| CommandOutput::Ok)
         }
         Command::ChangeClassFreq(name, freq) => {
-            change_freq(connection, name, freq).map(|()| "ok".to_string())
+            change_freq(connection, world, name, freq).map(|()| CommandOutput::Ok)
         }
         Command::ChangeClassActive(name, active) => {
-            change_active(connection, name, active).map(|()| "ok".to_string())
+            change_active(connection, world, name, active).map(|()| CommandOutput::Ok)
         }
         Command::ChangeClassUnique(name, unique) => {
-            change_unique(connection, name, unique).map(|()| "ok".to_string())
+            change_unique(connection, world, name, unique).map(|()| CommandOutput::Ok)
+        }
+        Command::ChangeClassVisit(name, visit) => {
+            change_visit(connection, world, name, visit).map(|()| CommandOutput::Ok)
+        }
+        Command::ChangeClassAnnounce(name, announce) => {
+            change_announce(connection, world, name, announce).map(|()| CommandOutput::Ok)
+        }
+        Command::ChangeClassArrival(name, message) => {
+            change_arrival(connection, world, name, message).map(|()| CommandOutput::Ok)
+        }
+        Command::ChangeClassDeparture(name, message) => {
+            change_departure(connection, world, name, message).map(|()| CommandOutput::Ok)
+        }
+        Command::ChangeInstanceName(id, name) => {
+            change_instance_name(connection, world, id, name).map(|()| CommandOutput::Ok)
+        }
+        Command::ChangeInstanceNote(id, note) => {
+            change_instance_note(connection, world, id, note).map(|()| CommandOutput::Ok)
+        }
+        Command::ChangeInstanceAttribute(id, key, value) => {
+            change_instance_attribute(connection, world, id, key, value)
+                .map(|()| CommandOutput::Ok)
         }
         Command::ChangeStarter(starter) => {
-            change_constant(connection, "starter".to_string(), starter).map(|()| "ok".to_string())
+            change_setting(connection, world, "starter", &starter).map(|()| CommandOutput::Ok)
+        }
+        Command::ChangeSetting(key, value) => {
+            change_setting(connection, world, &key, &value).map(|()| CommandOutput::Ok)
+        }
+        Command::ChangeTick(seconds) => {
+            change_tick(connection, world, seconds).map(|()| CommandOutput::Ok)
+        }
+        Command::ChangeClockRate(rate) => {
+            change_rate(connection, world, rate).map(|()| CommandOutput::Ok)
+        }
+        Command::ChangeClockTime(time) => {
+            change_time(connection, world, time).map(|()| CommandOutput::Ok)
+        }
+        Command::ChangeGuildPrefix(prefix) => {
+            change_guild_prefix(connection, scope.guild()?, prefix).map(|()| CommandOutput::Ok)
+        }
+        Command::ChangeGuildRole(role) => {
+            change_guild_role(connection, scope.guild()?, role).map(|()| CommandOutput::Ok)
+        }
+        Command::ChangeGuildWorld(name) => {
+            change_guild_world(connection, scope.guild()?, name).map(|()| CommandOutput::Ok)
+        }
+        Command::ChangeGuildChannel(channel) => {
+            change_guild_channel(connection, scope.guild()?, channel).map(|()| CommandOutput::Ok)
+        }
+        Command::Pause => pause(connection, world).map(|()| CommandOutput::Ok),
+        Command::Resume => resume(connection, world).map(|()| CommandOutput::Ok),
+        Command::Export(format, instances) => {
+            let snapshot = export_world(connection, &scope.world, instances)?;
+            Ok(CommandOutput::Document {
+                name: format!("{}.{}", scope.world.name, format.extension()),
+                content: to_document(&snapshot, format)?,
+            })
+        }
+        Command::Import(mode, document) => {
+            let snapshot = from_document(&document)?;
+            import_world(connection, world, snapshot, mode).map(CommandOutput::Import)
         }
         Command::FastForward(minutes) => {
-            fast_forward_instances(connection, chrono::Duration::minutes(minutes as i64))
-                .map(|()| "ok".to_string())
+            fast_forward_instances(connection, world, chrono::Duration::minutes(minutes as i64))
+                .map(|_report| CommandOutput::Ok)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use chrono::NaiveDateTime;
+    use command::parse_aliased;
+    use diesel::connection::SimpleConnection;
+    use diesel::QueryDsl;
+    use diesel::RunQueryDsl;
+    use models::Constant;
+    use models::NpcInstance;
+    use queries::get_world;
+    use schema::constants;
+    use schema::npc_classes;
+    use schema::npc_events;
+    use schema::npc_instances;
+    use test_connection;
+
+    type Rows = (Vec<Constant>, Vec<NpcClass>, Vec<NpcInstance>, i64);
+
+    fn run(
+        connection: &SqliteConnection,
+        scope: &Scope,
+        text: &str,
+    ) -> Result<CommandOutput, Error> {
+        let args = text.split_whitespace().map(str::to_owned).collect();
+        execute_command(connection, scope, parse_aliased(args, HashMap::new())?)
+    }
+
+    fn rows(connection: &SqliteConnection) -> Rows {
+        (
+            constants::table.load(connection).unwrap(),
+            npc_classes::table.load(connection).unwrap(),
+            npc_instances::table.load(connection).unwrap(),
+            npc_events::table.count().get_result(connection).unwrap(),
+        )
+    }
+
+    #[test]
+    fn failing_halfway_leaves_no_partial_rows() {
+        let connection = test_connection();
+        let world = get_world(&connection, "default".to_string()).unwrap();
+        let scope = Scope { world, guild: None };
+        // the highest frequency for 10 second ticks and 20 minute visits spawns on every tick
+        run(&connection, &scope, "add class guard 12000").unwrap();
+        run(&connection, &scope, "add class thief 12000").unwrap();
+        let before = rows(&connection);
+
+        // the clock jump and the spawns are written before the events fail
+        connection
+            .batch_execute(
+                "CREATE TRIGGER `injected` BEFORE INSERT ON `npc_events` \
+                 BEGIN SELECT RAISE(ABORT, 'injected failure'); END;",
+            )
+            .unwrap();
+        match run(&connection, &scope, "fast-forward 600") {
+            Err(Error::Database(message)) => assert!(message.contains("injected failure")),
+            other => panic!("expected the injected failure, got {:?}", other),
+        }
+        assert_eq!(rows(&connection), before);
+
+        connection.batch_execute("DROP TRIGGER `injected`;").unwrap();
+        run(&connection, &scope, "fast-forward 600").unwrap();
+        assert!(rows(&connection) != before);
+    }
+
+    fn event(kind: &str, instance: i32, hour: u32) -> NpcEvent {
+        NpcEvent {
+            id: 0,
+            world: 1,
+            kind: kind.to_string(),
+            class: 7,
+            class_name: "king".to_string(),
+            instance,
+            time: at(hour),
+        }
+    }
+
+    fn at(hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd(2018, 9, 3).and_hms(hour, 0, 0)
+    }
+
+    #[test]
+    fn collapsed_visits_count_once() {
+        let king = NpcClass {
+            id: 7,
+            name: "king".to_string(),
+            commonality: 100,
+            next_tick: at(0),
+            active: 1,
+            unique: 1,
+            visit_minutes: 60,
+            world: 1,
+            announce: 0,
+            arrival_message: None,
+            departure_message: None,
+        };
+        // visit 1 began before the counted events, 2 continued it and 3 and 4 were
+        // one new stay, 5 came and went on its own, 7 was shorter than 6 and
+        // collapsed into it
+        let events = vec![
+            event("spawned", 2, 1),
+            event("collapsed", 1, 1),
+            event("expired", 2, 2),
+            event("spawned", 3, 3),
+            event("spawned", 4, 4),
+            event("collapsed", 3, 4),
+            event("expired", 4, 5),
+            event("spawned", 5, 6),
+            event("removed", 5, 7),
+            event("spawned", 6, 8),
+            event("spawned", 7, 9),
+            event("collapsed", 7, 10),
+            event("expired", 6, 11),
+        ];
+        let stats = class_stats(king, &events);
+        assert_eq!(stats.visits, 3);
+        assert_eq!(stats.last_arrival, Some(at(8)));
+        assert_eq!(stats.last_departure, Some(at(11)));
+    }
+}
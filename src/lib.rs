@@ -8,22 +8,89 @@ extern crate diesel;
 extern crate byteorder;
 extern crate chrono;
 extern crate core;
+extern crate csv;
+extern crate diesel_migrations;
 extern crate rand;
 extern crate serde;
 extern crate serde_json;
+extern crate serde_yaml;
 extern crate serenity;
 extern crate shlex;
+extern crate toml;
 
+use diesel::connection::SimpleConnection;
+use diesel::r2d2::ConnectionManager;
+use diesel::r2d2::CustomizeConnection;
 use diesel::sqlite::SqliteConnection;
 use diesel::Connection;
+use diesel::QueryResult;
+use error::Error;
+use std::time::Duration;
 
+pub mod announce;
+pub mod bulk;
+pub mod clock;
 pub mod command;
+pub mod config;
+pub mod error;
 pub mod execute;
+pub mod migrate;
 pub mod models;
+pub mod output;
+pub mod permissions;
 pub mod queries;
+pub mod schedule;
 pub mod schema;
+pub mod settings;
+pub mod snapshot;
 pub mod timing;
 
-pub fn establish_connection(url: &str) -> Result<SqliteConnection, String> {
-    SqliteConnection::establish(&url).map_err(|_e| format!("Error connecting to {}", url))
+pub type Pool = diesel::r2d2::Pool<ConnectionManager<SqliteConnection>>;
+
+const POOL_SIZE: u32 = 4;
+const BUSY_TIMEOUT_MS: u32 = 5000;
+
+pub fn establish_connection(url: &str) -> Result<SqliteConnection, Error> {
+    let connection = SqliteConnection::establish(&url)
+        .map_err(|e| Error::Connection(format!("could not connect to {}: {}", url, e)))?;
+    configure(&connection)
+        .map_err(|e| Error::database(format!("could not configure {}", url), e))?;
+    Ok(connection)
+}
+
+/// Opens the connections up front, so a bad url fails at startup instead of
+/// on the first message.
+pub fn establish_pool(url: &str) -> Result<Pool, Error> {
+    diesel::r2d2::Pool::builder()
+        .max_size(POOL_SIZE)
+        .connection_timeout(Duration::from_secs(5))
+        .connection_customizer(Box::new(Pragmas))
+        .build(ConnectionManager::new(url))
+        .map_err(|e| Error::Connection(format!("could not connect to {}: {}", url, e)))
+}
+
+/// A migrated in-memory database for the tests.
+#[cfg(test)]
+pub fn test_connection() -> SqliteConnection {
+    let connection = establish_connection(":memory:").unwrap();
+    migrate::run_pending(&connection).unwrap();
+    connection
+}
+
+/// WAL lets readers run next to a writer, and the busy timeout makes
+/// concurrent writers wait for each other instead of failing as locked.
+fn configure(connection: &SqliteConnection) -> QueryResult<()> {
+    connection.batch_execute(&format!(
+        "PRAGMA busy_timeout = {}; PRAGMA journal_mode = WAL; PRAGMA foreign_keys = ON;",
+        BUSY_TIMEOUT_MS
+    ))
+}
+
+#[derive(Debug)]
+struct Pragmas;
+
+impl CustomizeConnection<SqliteConnection, diesel::r2d2::Error> for Pragmas {
+    fn on_acquire(&self, connection: &mut SqliteConnection) -> Result<(), diesel::r2d2::Error> {
+        configure(connection).map_err(diesel::r2d2::Error::QueryError)
+    }
 }
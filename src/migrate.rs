@@ -0,0 +1,191 @@
+use diesel;
+use diesel::connection::SimpleConnection;
+use diesel::migration::Migration;
+use diesel::migration::RunMigrationsError;
+use diesel::sql_types::Text;
+use diesel::sqlite::SqliteConnection;
+use diesel::Connection;
+use diesel::RunQueryDsl;
+use diesel_migrations::run_migrations;
+use diesel_migrations::setup_database;
+use diesel_migrations::MigrationConnection;
+use error::Error;
+use std::io;
+
+/// A migration compiled into the binary. `embed_migrations!` can only run
+/// migrations, keeping the down scripts here is what makes `migrate down` work.
+pub struct EmbeddedMigration {
+    pub name: &'static str,
+    version: &'static str,
+    up: &'static str,
+    down: &'static str,
+}
+
+impl Migration for EmbeddedMigration {
+    fn version(&self) -> &str {
+        self.version
+    }
+
+    fn run(&self, connection: &SimpleConnection) -> Result<(), RunMigrationsError> {
+        connection.batch_execute(self.up).map_err(Into::into)
+    }
+
+    fn revert(&self, connection: &SimpleConnection) -> Result<(), RunMigrationsError> {
+        connection.batch_execute(self.down).map_err(Into::into)
+    }
+}
+
+#[derive(QueryableByName)]
+struct ForeignKeyViolation {
+    #[sql_type = "Text"]
+    table: String,
+}
+
+// the version is the directory timestamp without dashes, as the diesel cli stores it
+macro_rules! migration {
+    ($name:expr, $version:expr) => {
+        EmbeddedMigration {
+            name: $name,
+            version: $version,
+            up: include_str!(concat!("../migrations/", $name, "/up.sql")),
+            down: include_str!(concat!("../migrations/", $name, "/down.sql")),
+        }
+    };
+}
+
+/// Every directory in `migrations/`, oldest first. New migrations must be added here.
+pub const MIGRATIONS: &[EmbeddedMigration] = &[
+    migration!("2018-07-12-035102_create_npcs", "20180712035102"),
+    migration!("2018-07-21-181530_add_visit_minutes", "20180721181530"),
+    migration!("2018-07-28-143012_create_schedules", "20180728143012"),
+    migration!("2018-08-04-101544_create_worlds", "20180804101544"),
+    migration!("2018-08-11-192207_create_guild_settings", "20180811192207"),
+    migration!("2018-08-18-121930_create_permissions", "20180818121930"),
+    migration!("2018-08-25-163045_add_announcements", "20180825163045"),
+    migration!("2018-09-01-110820_create_npc_events", "20180901110820"),
+    migration!("2018-09-08-094512_add_instance_details", "20180908094512"),
+    migration!("2018-09-15-143207_create_npc_names", "20180915143207"),
+    migration!("2018-09-22-104815_add_permission_guilds", "20180922104815"),
+];
+
+/// Lists every embedded migration and whether it has been applied.
+pub fn status(connection: &SqliteConnection) -> Result<Vec<(&'static str, bool)>, Error> {
+    setup_database(connection)
+        .map_err(|e| Error::database("could not create migrations table", e))?;
+    let applied = connection
+        .previously_run_migration_versions()
+        .map_err(|e| Error::database("could not query database for migrations", e))?;
+    Ok(MIGRATIONS
+        .iter()
+        .map(|m| (m.name, applied.contains(m.version)))
+        .collect())
+}
+
+/// Runs `f` the way sqlite documents for rebuilding tables: foreign keys are
+/// off, so dropping a referenced table does not cascade, and checked afterwards.
+/// The pragma is ignored inside a transaction, so it is set around them.
+fn without_foreign_keys<T, F>(connection: &SqliteConnection, f: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    connection
+        .batch_execute("PRAGMA foreign_keys = OFF;")
+        .map_err(|e| Error::database("could not disable foreign keys", e))?;
+    let result = f();
+    let violations = diesel::sql_query("PRAGMA foreign_key_check")
+        .load::<ForeignKeyViolation>(connection)
+        .map_err(|e| Error::database("could not check foreign keys", e));
+    connection
+        .batch_execute("PRAGMA foreign_keys = ON;")
+        .map_err(|e| Error::database("could not enable foreign keys", e))?;
+    let result = result?;
+    match violations?.first() {
+        Some(violation) => Err(Error::SchemaViolation(format!(
+            "migration left dangling references in {}",
+            violation.table
+        ))),
+        None => Ok(result),
+    }
+}
+
+/// Applies the pending migrations, each in its own transaction, and returns their names.
+pub fn run_pending(connection: &SqliteConnection) -> Result<Vec<&'static str>, Error> {
+    let pending = status(connection)?
+        .into_iter()
+        .filter(|&(_name, applied)| !applied)
+        .map(|(name, _applied)| name)
+        .collect::<Vec<_>>();
+    without_foreign_keys(connection, || {
+        run_migrations(
+            connection,
+            MIGRATIONS.iter().map(|m| m as &Migration),
+            &mut io::sink(),
+        )
+        .map_err(|e| Error::Database(format!("could not run migrations: {}", e)))
+    })?;
+    Ok(pending)
+}
+
+/// Reverts the most recently applied migration and returns its name.
+pub fn revert_latest(connection: &SqliteConnection) -> Result<&'static str, Error> {
+    let latest = connection
+        .latest_run_migration_version()
+        .map_err(|e| Error::database("could not query database for migrations", e))?
+        .ok_or_else(|| Error::NotFound("no migrations have been applied".to_string()))?;
+    let migration = MIGRATIONS
+        .iter()
+        .find(|m| m.version == latest)
+        .ok_or_else(|| {
+            Error::NotFound(format!("migration {} is not known to this binary", latest))
+        })?;
+    without_foreign_keys(connection, || {
+        let mut failure = None;
+        connection
+            .transaction::<_, diesel::result::Error, _>(|| {
+                migration.revert(connection).map_err(|e| {
+                    failure = Some(Error::Database(format!(
+                        "could not revert {}: {}",
+                        migration.name, e
+                    )));
+                    diesel::result::Error::RollbackTransaction
+                })?;
+                diesel::sql_query("DELETE FROM `__diesel_schema_migrations` WHERE `version` = ?")
+                    .bind::<Text, _>(migration.version)
+                    .execute(connection)?;
+                Ok(())
+            })
+            .map_err(|e| {
+                failure.unwrap_or_else(|| Error::database("could not revert migration", e))
+            })
+    })?;
+    Ok(migration.name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn every_migration_directory_is_embedded() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("migrations");
+        let mut names = fs::read_dir(directory)
+            .unwrap()
+            .map(|entry| entry.unwrap())
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        names.sort();
+        let embedded = MIGRATIONS.iter().map(|m| m.name.to_string()).collect::<Vec<_>>();
+        assert_eq!(embedded, names);
+    }
+
+    #[test]
+    fn versions_match_the_directory_timestamps() {
+        for migration in MIGRATIONS {
+            let timestamp = migration.name.split('_').next().unwrap().replace('-', "");
+            assert_eq!(migration.version, timestamp);
+        }
+    }
+}
@@ -1,5 +1,8 @@
 use chrono::NaiveDateTime;
-use schema::{admins, aliases, constants, npc_classes, npc_instances};
+use schema::{
+    aliases, constants, guild_settings, npc_classes, npc_events, npc_instances, npc_names,
+    npc_schedules, permissions, world_bindings, worlds,
+};
 
 #[derive(Serialize, Deserialize, Queryable, Debug, Eq, PartialEq, Ord, PartialOrd)]
 pub struct NpcClass {
@@ -8,7 +11,15 @@ pub struct NpcClass {
     pub commonality: i32,
     pub next_tick: NaiveDateTime,
     pub active: i32,
-    pub unique: i32
+    pub unique: i32,
+    pub visit_minutes: i32,
+    pub world: i32,
+    #[serde(default)]
+    pub announce: i32,
+    #[serde(default)]
+    pub arrival_message: Option<String>,
+    #[serde(default)]
+    pub departure_message: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Queryable, Debug, Eq, PartialEq, Ord, PartialOrd)]
@@ -16,6 +27,51 @@ pub struct NpcInstance {
     pub id: i32,
     pub class: i32,
     pub active_until: NaiveDateTime,
+    pub world: i32,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    /// A json object of free-form string attributes, such as a mood.
+    #[serde(default = "no_attributes")]
+    pub attributes: String,
+}
+
+pub fn no_attributes() -> String {
+    "{}".to_string()
+}
+
+/// A logged change of the instances: `spawned`, `expired`, `removed` or
+/// `collapsed` (an older visit of a unique class replaced by a newer one).
+/// Times are game times.
+#[derive(Serialize, Deserialize, Queryable, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct NpcEvent {
+    pub id: i32,
+    pub world: i32,
+    pub kind: String,
+    pub class: i32,
+    pub class_name: String,
+    pub instance: i32,
+    pub time: NaiveDateTime,
+}
+
+/// An entry of a class name table: a whole `name`, or a `syllable` that is
+/// combined with others when the class has no whole names.
+#[derive(Serialize, Deserialize, Queryable, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct NpcName {
+    pub id: i32,
+    pub class: i32,
+    pub kind: String,
+    pub value: String,
+}
+
+#[derive(Serialize, Deserialize, Queryable, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct NpcSchedule {
+    pub id: i32,
+    pub class: i32,
+    pub weekdays: i32,
+    pub start_minute: i32,
+    pub end_minute: i32,
 }
 
 #[derive(Serialize, Deserialize, Queryable, Debug, Eq, PartialEq, Ord, PartialOrd)]
@@ -23,6 +79,7 @@ pub struct Alias {
     pub id: i32,
     pub alias: String,
     pub command: String,
+    pub world: i32,
 }
 
 #[derive(Serialize, Deserialize, Queryable, Debug, Eq, PartialEq, Ord, PartialOrd)]
@@ -30,12 +87,40 @@ pub struct Constant {
     pub id: i32,
     pub key: String,
     pub value: String,
+    pub world: i32,
+}
+
+#[derive(Serialize, Deserialize, Queryable, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Permission {
+    pub id: i32,
+    pub kind: String,
+    pub subject: String,
+    pub level: String,
+    #[serde(default)]
+    pub guild: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Queryable, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct World {
+    pub id: i32,
+    pub name: String,
 }
 
 #[derive(Serialize, Deserialize, Queryable, Debug, Eq, PartialEq, Ord, PartialOrd)]
-pub struct Admin {
+pub struct WorldBinding {
     pub id: i32,
-    pub user_id: String,
+    pub channel_id: String,
+    pub world: i32,
+}
+
+#[derive(Serialize, Deserialize, Queryable, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct GuildSettings {
+    pub id: i32,
+    pub guild_id: String,
+    pub prefix: Option<String>,
+    pub admin_role: Option<String>,
+    pub world: Option<i32>,
+    pub output_channel: Option<String>,
 }
 
 #[derive(Insertable, Debug)]
@@ -45,7 +130,12 @@ pub struct NewNpcClass<'a> {
     pub commonality: i32,
     pub next_tick: NaiveDateTime,
     pub active: i32,
-    pub unique: i32
+    pub unique: i32,
+    pub visit_minutes: i32,
+    pub world: i32,
+    pub announce: i32,
+    pub arrival_message: Option<&'a str>,
+    pub departure_message: Option<&'a str>,
 }
 
 #[derive(Insertable, Debug)]
@@ -53,6 +143,38 @@ pub struct NewNpcClass<'a> {
 pub struct NewNpcInstance {
     pub class: i32,
     pub active_until: NaiveDateTime,
+    pub world: i32,
+    pub name: Option<String>,
+    pub notes: Option<String>,
+    pub attributes: String,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "npc_events"]
+pub struct NewNpcEvent<'a> {
+    pub world: i32,
+    pub kind: &'a str,
+    pub class: i32,
+    pub class_name: &'a str,
+    pub instance: i32,
+    pub time: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "npc_names"]
+pub struct NewNpcName<'a> {
+    pub class: i32,
+    pub kind: &'a str,
+    pub value: &'a str,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "npc_schedules"]
+pub struct NewNpcSchedule {
+    pub class: i32,
+    pub weekdays: i32,
+    pub start_minute: i32,
+    pub end_minute: i32,
 }
 
 #[derive(Insertable, Debug)]
@@ -60,6 +182,7 @@ pub struct NewNpcInstance {
 pub struct NewAlias<'a> {
     pub alias: &'a str,
     pub command: &'a str,
+    pub world: i32,
 }
 
 #[derive(Insertable, Debug)]
@@ -67,10 +190,33 @@ pub struct NewAlias<'a> {
 pub struct NewConstant<'a> {
     pub key: &'a str,
     pub value: &'a str,
+    pub world: i32,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "permissions"]
+pub struct NewPermission<'a> {
+    pub kind: &'a str,
+    pub subject: &'a str,
+    pub level: &'a str,
+    pub guild: Option<&'a str>,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "worlds"]
+pub struct NewWorld<'a> {
+    pub name: &'a str,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "world_bindings"]
+pub struct NewWorldBinding<'a> {
+    pub channel_id: &'a str,
+    pub world: i32,
 }
 
 #[derive(Insertable, Debug)]
-#[table_name = "admins"]
-pub struct NewAdmin<'a> {
-    pub user_id: &'a str,
+#[table_name = "guild_settings"]
+pub struct NewGuildSettings<'a> {
+    pub guild_id: &'a str,
 }
@@ -0,0 +1,644 @@
+use chrono::NaiveDateTime;
+use error::Error;
+use models::NpcClass;
+use models::NpcEvent;
+use models::NpcInstance;
+use models::NpcName;
+use models::NpcSchedule;
+use models::Permission;
+use queries::parse_attributes;
+use schedule::format_schedule;
+use serde_json;
+use snapshot::ImportReport;
+use std::str::FromStr;
+
+const CSV_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Discord,
+    Text,
+    Json,
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Format, Error> {
+        match s {
+            "discord" => Ok(Format::Discord),
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            other => Err(Error::Parse(format!(
+                "invalid output format: {} (discord | json | text | csv)",
+                other
+            ))),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct InstanceCount {
+    pub class_name: String,
+    pub count: usize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct InstanceEntry {
+    #[serde(flatten)]
+    pub instance: NpcInstance,
+    pub class_name: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ScheduleEntry {
+    #[serde(flatten)]
+    pub schedule: NpcSchedule,
+    pub class_name: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AliasEntry {
+    pub alias: String,
+    pub command: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WorldEntry {
+    pub name: String,
+    pub current: bool,
+    pub channels: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct GuildEntry {
+    pub prefix: Option<String>,
+    pub admin_role: Option<String>,
+    pub world: Option<String>,
+    pub output_channel: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SettingEntry {
+    pub key: String,
+    pub value: Option<String>,
+    pub default: Option<String>,
+    pub description: String,
+}
+
+/// Visits of a class since the start of the `show stats` period. Visits merged
+/// into a newer one of a unique class are not counted twice.
+#[derive(Serialize, Debug)]
+pub struct StatEntry {
+    pub class_name: String,
+    pub visits: usize,
+    pub last_arrival: Option<NaiveDateTime>,
+    pub last_departure: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum CommandOutput {
+    Ok,
+    Deleted(usize),
+    InstanceSummary(Vec<InstanceCount>),
+    InstanceList(Vec<InstanceEntry>),
+    ClassList(Vec<NpcClass>),
+    ScheduleList(Vec<ScheduleEntry>),
+    NameList(Vec<NpcName>),
+    Clock {
+        time: NaiveDateTime,
+        rate: f64,
+        paused: bool,
+    },
+    WorldList(Vec<WorldEntry>),
+    Guild(GuildEntry),
+    PermissionList(Vec<Permission>),
+    AliasList(Vec<AliasEntry>),
+    SettingList(Vec<SettingEntry>),
+    EventList(Vec<NpcEvent>),
+    StatList {
+        since: NaiveDateTime,
+        stats: Vec<StatEntry>,
+    },
+    Document {
+        name: String,
+        content: String,
+    },
+    Import(ImportReport),
+    ClassesAdded(Vec<String>),
+}
+
+/// Renders for a discord channel, channels and roles become mentions.
+pub fn render_discord(output: &CommandOutput) -> String {
+    render_lines(
+        output,
+        |id| format!("<#{}>", id),
+        |id| format!("<@&{}>", id),
+    )
+}
+
+/// Renders for a terminal, channels and roles are shown by id.
+pub fn render_text(output: &CommandOutput) -> String {
+    render_lines(
+        output,
+        |id| format!("channel {}", id),
+        |id| format!("role {}", id),
+    )
+}
+
+pub fn render(output: &CommandOutput, format: Format) -> Result<String, Error> {
+    match format {
+        Format::Discord => Ok(render_discord(output)),
+        Format::Text => Ok(render_text(output)),
+        Format::Json => render_json(output),
+        Format::Csv => Ok(render_csv(output)),
+    }
+}
+
+pub fn render_json(output: &CommandOutput) -> Result<String, Error> {
+    serde_json::to_string_pretty(output)
+        .map_err(|e| Error::Invalid(format!("could not serialize output: {}", e.to_string())))
+}
+
+/// Renders one header row followed by one row per entry, for spreadsheets and scripts.
+pub fn render_csv(output: &CommandOutput) -> String {
+    let (header, rows): (&[&str], Vec<Vec<String>>) = match output {
+        &CommandOutput::Ok => (&["result"], vec![vec!["ok".to_string()]]),
+        &CommandOutput::Deleted(count) => (&["deleted"], vec![vec![count.to_string()]]),
+        &CommandOutput::InstanceSummary(ref counts) => (
+            &["class_name", "count"],
+            counts
+                .iter()
+                .map(|entry| vec![entry.class_name.clone(), entry.count.to_string()])
+                .collect(),
+        ),
+        &CommandOutput::InstanceList(ref instances) => (
+            &[
+                "id",
+                "class",
+                "class_name",
+                "active_until",
+                "world",
+                "name",
+                "notes",
+                "attributes",
+            ],
+            instances
+                .iter()
+                .map(|entry| {
+                    vec![
+                        entry.instance.id.to_string(),
+                        entry.instance.class.to_string(),
+                        entry.class_name.clone(),
+                        entry
+                            .instance
+                            .active_until
+                            .format(CSV_TIME_FORMAT)
+                            .to_string(),
+                        entry.instance.world.to_string(),
+                        entry.instance.name.clone().unwrap_or_default(),
+                        entry.instance.notes.clone().unwrap_or_default(),
+                        entry.instance.attributes.clone(),
+                    ]
+                })
+                .collect(),
+        ),
+        &CommandOutput::ClassList(ref classes) => (
+            &[
+                "id",
+                "name",
+                "commonality",
+                "next_tick",
+                "active",
+                "unique",
+                "visit_minutes",
+                "world",
+                "announce",
+                "arrival_message",
+                "departure_message",
+            ],
+            classes
+                .iter()
+                .map(|class| {
+                    vec![
+                        class.id.to_string(),
+                        class.name.clone(),
+                        class.commonality.to_string(),
+                        class.next_tick.format(CSV_TIME_FORMAT).to_string(),
+                        class.active.to_string(),
+                        class.unique.to_string(),
+                        class.visit_minutes.to_string(),
+                        class.world.to_string(),
+                        class.announce.to_string(),
+                        class.arrival_message.clone().unwrap_or_default(),
+                        class.departure_message.clone().unwrap_or_default(),
+                    ]
+                })
+                .collect(),
+        ),
+        &CommandOutput::ScheduleList(ref schedules) => (
+            &[
+                "id",
+                "class",
+                "class_name",
+                "weekdays",
+                "start_minute",
+                "end_minute",
+            ],
+            schedules
+                .iter()
+                .map(|entry| {
+                    vec![
+                        entry.schedule.id.to_string(),
+                        entry.schedule.class.to_string(),
+                        entry.class_name.clone(),
+                        entry.schedule.weekdays.to_string(),
+                        entry.schedule.start_minute.to_string(),
+                        entry.schedule.end_minute.to_string(),
+                    ]
+                })
+                .collect(),
+        ),
+        &CommandOutput::NameList(ref names) => (
+            &["id", "class", "kind", "value"],
+            names
+                .iter()
+                .map(|n| {
+                    vec![
+                        n.id.to_string(),
+                        n.class.to_string(),
+                        n.kind.clone(),
+                        n.value.clone(),
+                    ]
+                })
+                .collect(),
+        ),
+        &CommandOutput::Clock { time, rate, paused } => (
+            &["time", "rate", "paused"],
+            vec![vec![
+                time.format(CSV_TIME_FORMAT).to_string(),
+                rate.to_string(),
+                paused.to_string(),
+            ]],
+        ),
+        &CommandOutput::WorldList(ref worlds) => (
+            &["name", "current", "channels"],
+            worlds
+                .iter()
+                .map(|w| vec![w.name.clone(), w.current.to_string(), w.channels.join(" ")])
+                .collect(),
+        ),
+        &CommandOutput::Guild(ref guild) => (
+            &["prefix", "admin_role", "world", "output_channel"],
+            vec![vec![
+                guild.prefix.clone().unwrap_or_default(),
+                guild.admin_role.clone().unwrap_or_default(),
+                guild.world.clone().unwrap_or_default(),
+                guild.output_channel.clone().unwrap_or_default(),
+            ]],
+        ),
+        &CommandOutput::PermissionList(ref permissions) => (
+            &["id", "kind", "subject", "level", "guild"],
+            permissions
+                .iter()
+                .map(|p| {
+                    vec![
+                        p.id.to_string(),
+                        p.kind.clone(),
+                        p.subject.clone(),
+                        p.level.clone(),
+                        p.guild.clone().unwrap_or_default(),
+                    ]
+                })
+                .collect(),
+        ),
+        &CommandOutput::AliasList(ref aliases) => (
+            &["alias", "command"],
+            aliases
+                .iter()
+                .map(|a| vec![a.alias.clone(), a.command.join(" ")])
+                .collect(),
+        ),
+        &CommandOutput::EventList(ref events) => (
+            &["id", "kind", "class", "class_name", "instance", "time"],
+            events
+                .iter()
+                .map(|e| {
+                    vec![
+                        e.id.to_string(),
+                        e.kind.clone(),
+                        e.class.to_string(),
+                        e.class_name.clone(),
+                        e.instance.to_string(),
+                        e.time.format(CSV_TIME_FORMAT).to_string(),
+                    ]
+                })
+                .collect(),
+        ),
+        &CommandOutput::StatList { ref stats, .. } => (
+            &["class_name", "visits", "last_arrival", "last_departure"],
+            stats
+                .iter()
+                .map(|s| {
+                    vec![
+                        s.class_name.clone(),
+                        s.visits.to_string(),
+                        s.last_arrival
+                            .map(|t| t.format(CSV_TIME_FORMAT).to_string())
+                            .unwrap_or_default(),
+                        s.last_departure
+                            .map(|t| t.format(CSV_TIME_FORMAT).to_string())
+                            .unwrap_or_default(),
+                    ]
+                })
+                .collect(),
+        ),
+        &CommandOutput::SettingList(ref settings) => (
+            &["key", "value", "default", "description"],
+            settings
+                .iter()
+                .map(|s| {
+                    vec![
+                        s.key.clone(),
+                        s.value.clone().unwrap_or_default(),
+                        s.default.clone().unwrap_or_default(),
+                        s.description.clone(),
+                    ]
+                })
+                .collect(),
+        ),
+        &CommandOutput::Document { ref content, .. } => return content.clone(),
+        &CommandOutput::Import(ref report) => (
+            &["entry", "value"],
+            import_counts(report)
+                .into_iter()
+                .map(|(entry, count)| vec![entry.to_string(), count.to_string()])
+                .chain(
+                    report
+                        .conflicts
+                        .iter()
+                        .map(|c| vec!["skipped".to_string(), c.clone()]),
+                )
+                .collect(),
+        ),
+        &CommandOutput::ClassesAdded(ref names) => (
+            &["name"],
+            names.iter().map(|name| vec![name.clone()]).collect(),
+        ),
+    };
+    let mut result = csv_row(header.iter().map(|h| h.to_string()));
+    for row in rows {
+        result.push('\n');
+        result.push_str(&csv_row(row.into_iter()));
+    }
+    result
+}
+
+fn csv_row<I: Iterator<Item = String>>(fields: I) -> String {
+    fields
+        .map(|field| {
+            if field.contains(',') || field.contains('"') || field.contains('\n') {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+fn render_lines<C, R>(output: &CommandOutput, channel: C, role: R) -> String
+where
+    C: Fn(&str) -> String,
+    R: Fn(&str) -> String,
+{
+    match output {
+        &CommandOutput::Ok => "ok".to_string(),
+        &CommandOutput::Deleted(count) => format!("deleted instances: {}", count),
+        &CommandOutput::InstanceSummary(ref counts) => lines(
+            counts
+                .iter()
+                .map(|entry| format!("{}: {}", entry.class_name, entry.count)),
+            "no npcs!",
+        ),
+        &CommandOutput::InstanceList(ref instances) => lines(
+            instances.iter().map(|entry| {
+                let mut result = format!("id: {}, {}", entry.instance.id, entry.class_name);
+                if let Some(ref name) = entry.instance.name {
+                    result.push_str(&format!(" \"{}\"", name));
+                }
+                result.push_str(&format!(
+                    ", active until {}",
+                    entry.instance.active_until.format("%a %H:%M:%S")
+                ));
+                // a broken attributes entry is left out rather than failing the listing
+                if let Ok(attributes) = parse_attributes(&entry.instance.attributes) {
+                    for (key, value) in attributes {
+                        result.push_str(&format!(", {}: {}", key, value));
+                    }
+                }
+                if let Some(ref notes) = entry.instance.notes {
+                    result.push_str(&format!("\n    {}", notes));
+                }
+                result
+            }),
+            "no npcs!",
+        ),
+        &CommandOutput::ClassList(ref classes) => lines(
+            classes.iter().map(|i| {
+                format!(
+                    "{}, frequency: {}, active: {}, visit: {} min{}",
+                    i.name,
+                    i.commonality,
+                    i.active > 0,
+                    i.visit_minutes,
+                    if i.announce > 0 { ", announced" } else { "" }
+                )
+            }),
+            "no npc classes!",
+        ),
+        &CommandOutput::ScheduleList(ref schedules) => lines(
+            schedules.iter().map(|entry| {
+                format!(
+                    "id: {}, {}, {}",
+                    entry.schedule.id,
+                    entry.class_name,
+                    format_schedule(&entry.schedule)
+                )
+            }),
+            "no schedules, npcs can appear at any time!",
+        ),
+        &CommandOutput::NameList(ref names) => lines(
+            ["name", "syllable"].iter().filter_map(|kind| {
+                let values = names
+                    .iter()
+                    .filter(|n| &n.kind == kind)
+                    .map(|n| n.value.as_ref())
+                    .collect::<Vec<&str>>();
+                if values.is_empty() {
+                    None
+                } else {
+                    Some(format!("{}s: {}", kind, values.join(", ")))
+                }
+            }),
+            "no names, instances stay anonymous!",
+        ),
+        &CommandOutput::Clock { time, rate, paused } => format!(
+            "game time: {}, rate: {}x{}",
+            time.format("%Y-%m-%d %a %H:%M:%S"),
+            rate,
+            if paused { ", paused" } else { "" }
+        ),
+        &CommandOutput::WorldList(ref worlds) => lines(
+            worlds.iter().map(|w| {
+                format!(
+                    "{}{}{}",
+                    w.name,
+                    if w.current { " (current)" } else { "" },
+                    if w.channels.is_empty() {
+                        String::new()
+                    } else {
+                        format!(
+                            ", channels: {}",
+                            w.channels
+                                .iter()
+                                .map(|c| channel(c))
+                                .collect::<Vec<String>>()
+                                .join(" ")
+                        )
+                    }
+                )
+            }),
+            "no worlds!",
+        ),
+        &CommandOutput::Guild(ref guild) => format!(
+            "prefix: {}\nadmin role: {}\nworld: {}\noutput channel: {}",
+            guild.prefix.clone().unwrap_or_else(|| "none".to_string()),
+            guild
+                .admin_role
+                .as_ref()
+                .map(|r| role(r))
+                .unwrap_or_else(|| "none".to_string()),
+            guild.world.clone().unwrap_or_else(|| "none".to_string()),
+            guild
+                .output_channel
+                .as_ref()
+                .map(|c| channel(c))
+                .unwrap_or_else(|| "none".to_string()),
+        ),
+        &CommandOutput::PermissionList(ref permissions) => lines(
+            permissions
+                .iter()
+                .map(|p| match p.guild {
+                    Some(ref guild) => format!(
+                        "{} {}: {} in guild {}",
+                        p.kind, p.subject, p.level, guild
+                    ),
+                    None => format!("{} {}: {}", p.kind, p.subject, p.level),
+                }),
+            "no permissions granted!",
+        ),
+        &CommandOutput::AliasList(ref aliases) => lines(
+            aliases
+                .iter()
+                .map(|a| format!("{}: {}", a.alias, a.command.join(" "))),
+            "no aliases!",
+        ),
+        &CommandOutput::EventList(ref events) => lines(
+            events.iter().map(|e| {
+                let what = match e.kind.as_ref() {
+                    "spawned" => "arrived",
+                    "expired" => "left",
+                    "collapsed" => "visit merged into a newer one",
+                    "removed" => "was removed",
+                    other => other,
+                };
+                format!(
+                    "{}: {} {} (id: {})",
+                    e.time.format("%Y-%m-%d %a %H:%M"),
+                    e.class_name,
+                    what,
+                    e.instance
+                )
+            }),
+            "no changes!",
+        ),
+        &CommandOutput::StatList {
+            ref since,
+            ref stats,
+        } => format!(
+            "since {}:\n{}",
+            since.format("%Y-%m-%d %a %H:%M"),
+            lines(
+                stats.iter().map(|s| {
+                    let mut result = format!("{}: {} visits", s.class_name, s.visits);
+                    if let Some(time) = s.last_arrival {
+                        result.push_str(&format!(
+                            ", last arrived {}",
+                            time.format("%Y-%m-%d %H:%M")
+                        ));
+                    }
+                    if let Some(time) = s.last_departure {
+                        result.push_str(&format!(", last left {}", time.format("%Y-%m-%d %H:%M")));
+                    }
+                    result
+                }),
+                "no classes!",
+            )
+        ),
+        &CommandOutput::SettingList(ref settings) => lines(
+            settings.iter().map(|s| {
+                let value = match (&s.value, &s.default) {
+                    (&Some(ref value), _) => value.clone(),
+                    (&None, &Some(ref default)) => format!("{} (default)", default),
+                    (&None, &None) => "unset".to_string(),
+                };
+                format!("{}: {} - {}", s.key, value, s.description)
+            }),
+            "no settings!",
+        ),
+        &CommandOutput::Document { ref content, .. } => content.clone(),
+        &CommandOutput::Import(ref report) => {
+            let mut result = format!(
+                "imported: {}",
+                import_counts(report)
+                    .into_iter()
+                    .map(|(entry, count)| format!("{} {}", count, entry))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            );
+            if !report.conflicts.is_empty() {
+                result.push_str("\nskipped:\n");
+                result.push_str(&report.conflicts.join("\n"));
+            }
+            result
+        }
+        &CommandOutput::ClassesAdded(ref names) => {
+            format!("added {} classes: {}", names.len(), names.join(", "))
+        }
+    }
+}
+
+fn import_counts(report: &ImportReport) -> Vec<(&'static str, usize)> {
+    vec![
+        ("classes", report.classes),
+        ("schedules", report.schedules),
+        ("names", report.names),
+        ("aliases", report.aliases),
+        ("constants", report.constants),
+        ("permissions", report.permissions),
+        ("instances", report.instances),
+    ]
+}
+
+fn lines<I: Iterator<Item = String>>(entries: I, empty: &str) -> String {
+    let result = entries.collect::<Vec<String>>();
+    if result.len() == 0 {
+        empty.to_string()
+    } else {
+        result.join("\n")
+    }
+}
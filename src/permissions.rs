@@ -0,0 +1,90 @@
+use error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Level {
+    Viewer,
+    Gm,
+    Owner,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            &Level::Viewer => "viewer",
+            &Level::Gm => "gm",
+            &Level::Owner => "owner",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Level {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Level, Error> {
+        match s {
+            "viewer" => Ok(Level::Viewer),
+            "gm" => Ok(Level::Gm),
+            "owner" => Ok(Level::Owner),
+            other => Err(Error::Parse(format!(
+                "invalid permission level: {} (viewer | gm | owner)",
+                other
+            ))),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Grantee {
+    User(u64),
+    Role(u64),
+}
+
+impl Grantee {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            &Grantee::User(_) => "user",
+            &Grantee::Role(_) => "role",
+        }
+    }
+
+    pub fn subject(&self) -> String {
+        match self {
+            &Grantee::User(id) | &Grantee::Role(id) => id.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Grantee {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.kind(), self.subject())
+    }
+}
+
+impl FromStr for Grantee {
+    type Err = Error;
+
+    fn from_str(mention: &str) -> Result<Grantee, Error> {
+        let (slice, role) = if mention.starts_with("<@&") && mention.ends_with(">") {
+            (&mention[3..(mention.len() - 1)], true)
+        } else if mention.starts_with("<@!") && mention.ends_with(">") {
+            (&mention[3..(mention.len() - 1)], false)
+        } else if mention.starts_with("<@") && mention.ends_with(">") {
+            (&mention[2..(mention.len() - 1)], false)
+        } else {
+            return Err(Error::Parse(format!(
+                "could not parse user or role mention: {}",
+                mention
+            )));
+        };
+        let id = u64::from_str(slice)
+            .map_err(|e| Error::Parse(format!("could not parse mention: {}", e.to_string())))?;
+        Ok(if role {
+            Grantee::Role(id)
+        } else {
+            Grantee::User(id)
+        })
+    }
+}
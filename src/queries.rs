@@ -1,197 +1,588 @@
-use chrono::Utc;
+use chrono::NaiveDateTime;
+use clock;
 use diesel;
 use diesel::sqlite::SqliteConnection;
 use diesel::ExpressionMethods;
 use diesel::QueryDsl;
 use diesel::RunQueryDsl;
+use error::Error;
 use models::Alias;
 use models::Constant;
-use models::NewAdmin;
+use models::GuildSettings;
 use models::NewAlias;
+use models::NewConstant;
+use models::NewGuildSettings;
 use models::NewNpcClass;
+use models::NewNpcName;
+use models::NewNpcSchedule;
+use models::NewPermission;
+use models::NewWorld;
+use models::NewWorldBinding;
 use models::NpcClass;
+use models::NpcEvent;
 use models::NpcInstance;
-use schema::{admins, aliases, constants, npc_classes, npc_instances};
+use models::NpcName;
+use models::NpcSchedule;
+use models::Permission;
+use permissions::Grantee;
+use permissions::Level;
+use models::World;
+use models::WorldBinding;
+use schema::{
+    aliases, constants, guild_settings, npc_classes, npc_events, npc_instances, npc_names,
+    npc_schedules, permissions, world_bindings, worlds,
+};
 use serde_json;
+use std::cmp::max;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
-use models::Admin;
+use std::str::FromStr;
+use timing::check_rate;
+use timing::get_tick;
+
+pub const DEFAULT_WORLD: &str = "default";
 
 pub fn add_class(
     connection: &SqliteConnection,
+    world: i32,
     name: String,
     commonality: i32,
     active: bool,
     unique: bool,
-) -> Result<(), String> {
-    let time = Utc::now().naive_utc();
+    visit_minutes: i32,
+) -> Result<(), Error> {
+    check_rate(commonality, visit_minutes, &get_tick(connection, world)?)?;
+    let time = clock::now(connection, world)?;
     let class = NewNpcClass {
         name: &name,
         commonality,
         next_tick: time,
         active: if active { 1 } else { 0 },
         unique: if unique { 1 } else { 0 },
+        visit_minutes,
+        world,
+        announce: 0,
+        arrival_message: None,
+        departure_message: None,
     };
     diesel::insert_into(npc_classes::table)
         .values(&class)
         .execute(connection)
-        .map_err(|e| format!("could not insert new npc: {}", e.to_string()))?;
+        .map_err(|e| Error::database("could not insert new npc", e))?;
     Ok(())
 }
 
 pub fn change_active(
     connection: &SqliteConnection,
+    world: i32,
     name: String,
     active: bool,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     let de = if active { "" } else { "de" };
-    let time = Utc::now().naive_utc();
+    let time = clock::now(connection, world)?;
     let npcs = diesel::update(npc_classes::table)
+        .filter(npc_classes::dsl::world.eq(world))
         .filter(npc_classes::dsl::name.eq(name.clone()))
         .set((
             npc_classes::dsl::active.eq(if active { 1 } else { 0 }),
             npc_classes::dsl::next_tick.eq(time),
         ))
         .execute(connection)
-        .map_err(|e| format!("could not {}activate npc: {}", de, e.to_string()))?;
+        .map_err(|e| Error::database(format!("could not {}activate npc", de), e))?;
     match npcs {
-        0 => Err(format!("could not find npc: {}", name)),
+        0 => Err(Error::NotFound(format!("could not find npc: {}", name))),
         1 => Ok(()),
-        _ => Err(format!("schema violation? {} npcs {}activated", npcs, de)),
+        _ => Err(Error::SchemaViolation(format!(
+            "{} npcs {}activated",
+            npcs, de
+        ))),
     }
 }
 
 pub fn change_unique(
     connection: &SqliteConnection,
+    world: i32,
     name: String,
     unique: bool,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     let non = if unique { "" } else { "non-" };
     let npcs = diesel::update(npc_classes::table)
+        .filter(npc_classes::dsl::world.eq(world))
         .filter(npc_classes::dsl::name.eq(name.clone()))
         .set(npc_classes::dsl::unique.eq(if unique { 1 } else { 0 }))
         .execute(connection)
-        .map_err(|e| format!("could not make npc {}unique: {}", non, e.to_string()))?;
+        .map_err(|e| Error::database(format!("could not make npc {}unique", non), e))?;
     match npcs {
-        0 => Err(format!("could not find npc: {}", name)),
+        0 => Err(Error::NotFound(format!("could not find npc: {}", name))),
         1 => Ok(()),
-        _ => Err(format!("schema violation? {} npcs made {}unique", npcs, non)),
+        _ => Err(Error::SchemaViolation(format!(
+            "{} npcs made {}unique",
+            npcs, non
+        ))),
     }
 }
 
 
 pub fn change_name(
     connection: &SqliteConnection,
+    world: i32,
     old_name: String,
     new_name: String,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     let npcs = diesel::update(npc_classes::table)
+        .filter(npc_classes::dsl::world.eq(world))
         .filter(npc_classes::dsl::name.eq(old_name.clone()))
         .set(npc_classes::dsl::name.eq(new_name))
         .execute(connection)
-        .map_err(|e| format!("could not change npcs name: {}", e.to_string()))?;
+        .map_err(|e| Error::database("could not change npcs name", e))?;
     match npcs {
-        0 => Err(format!("could not find npc: {}", old_name)),
+        0 => Err(Error::NotFound(format!("could not find npc: {}", old_name))),
         1 => Ok(()),
-        _ => Err(format!("schema violation? {} npcs renamed", npcs)),
+        _ => Err(Error::SchemaViolation(format!("{} npcs renamed", npcs))),
     }
 }
 
-pub fn change_freq(connection: &SqliteConnection, name: String, freq: i32) -> Result<(), String> {
+pub fn change_freq(
+    connection: &SqliteConnection,
+    world: i32,
+    name: String,
+    freq: i32,
+) -> Result<(), Error> {
+    let class = get_class(connection, world, name.clone())?;
+    check_rate(freq, class.visit_minutes, &get_tick(connection, world)?)?;
     let npcs = diesel::update(npc_classes::table)
+        .filter(npc_classes::dsl::world.eq(world))
         .filter(npc_classes::dsl::name.eq(name.clone()))
         .set(npc_classes::dsl::commonality.eq(freq))
         .execute(connection)
-        .map_err(|e| format!("could not change npcs frequency: {}", e.to_string()))?;
+        .map_err(|e| Error::database("could not change npcs frequency", e))?;
     match npcs {
-        0 => Err(format!("could not find npc: {}", name)),
+        0 => Err(Error::NotFound(format!("could not find npc: {}", name))),
         1 => Ok(()),
-        _ => Err(format!("schema violation? {} npcs modified", npcs)),
+        _ => Err(Error::SchemaViolation(format!("{} npcs modified", npcs))),
     }
 }
 
-pub fn remove_class(connection: &SqliteConnection, name: String) -> Result<(), String> {
-    let npcs = diesel::delete(npc_classes::table)
+pub fn change_visit(
+    connection: &SqliteConnection,
+    world: i32,
+    name: String,
+    visit_minutes: i32,
+) -> Result<(), Error> {
+    let class = get_class(connection, world, name.clone())?;
+    check_rate(class.commonality, visit_minutes, &get_tick(connection, world)?)?;
+    let npcs = diesel::update(npc_classes::table)
+        .filter(npc_classes::dsl::world.eq(world))
         .filter(npc_classes::dsl::name.eq(name.clone()))
+        .set(npc_classes::dsl::visit_minutes.eq(visit_minutes))
         .execute(connection)
-        .map_err(|e| format!("could not delete npc: {}", e.to_string()))?;
+        .map_err(|e| Error::database("could not change npcs visit time", e))?;
     match npcs {
-        0 => Err(format!("could not find npc: {}", name)),
+        0 => Err(Error::NotFound(format!("could not find npc: {}", name))),
         1 => Ok(()),
-        _ => Err(format!("schema violation? {} npcs deleted", npcs)),
+        _ => Err(Error::SchemaViolation(format!("{} npcs modified", npcs))),
     }
 }
 
-pub fn remove_instance(connection: &SqliteConnection, id: i32) -> Result<(), String> {
-    let npcs = diesel::delete(npc_instances::table)
-        .filter(npc_instances::dsl::id.eq(id))
+pub fn change_announce(
+    connection: &SqliteConnection,
+    world: i32,
+    name: String,
+    announce: bool,
+) -> Result<(), Error> {
+    let npcs = diesel::update(npc_classes::table)
+        .filter(npc_classes::dsl::world.eq(world))
+        .filter(npc_classes::dsl::name.eq(name.clone()))
+        .set(npc_classes::dsl::announce.eq(if announce { 1 } else { 0 }))
         .execute(connection)
-        .map_err(|e| format!("could not delete instance: {}", e.to_string()))?;
+        .map_err(|e| Error::database("could not change npcs announcements", e))?;
     match npcs {
-        0 => Err(format!("could not find instance: {}", id)),
+        0 => Err(Error::NotFound(format!("could not find npc: {}", name))),
         1 => Ok(()),
-        _ => Err(format!("schema violation? {} instances deleted", npcs)),
+        _ => Err(Error::SchemaViolation(format!("{} npcs modified", npcs))),
     }
 }
 
-pub fn get_classes(connection: &SqliteConnection) -> Result<Vec<NpcClass>, String> {
-    npc_classes::table.load(connection).map_err(|e| {
-        format!(
-            "could not query database for npc classes: {}",
+pub fn change_arrival(
+    connection: &SqliteConnection,
+    world: i32,
+    name: String,
+    message: Option<String>,
+) -> Result<(), Error> {
+    let npcs = diesel::update(npc_classes::table)
+        .filter(npc_classes::dsl::world.eq(world))
+        .filter(npc_classes::dsl::name.eq(name.clone()))
+        .set(npc_classes::dsl::arrival_message.eq(message))
+        .execute(connection)
+        .map_err(|e| Error::database("could not change npcs arrival message", e))?;
+    match npcs {
+        0 => Err(Error::NotFound(format!("could not find npc: {}", name))),
+        1 => Ok(()),
+        _ => Err(Error::SchemaViolation(format!("{} npcs modified", npcs))),
+    }
+}
+
+pub fn change_departure(
+    connection: &SqliteConnection,
+    world: i32,
+    name: String,
+    message: Option<String>,
+) -> Result<(), Error> {
+    let npcs = diesel::update(npc_classes::table)
+        .filter(npc_classes::dsl::world.eq(world))
+        .filter(npc_classes::dsl::name.eq(name.clone()))
+        .set(npc_classes::dsl::departure_message.eq(message))
+        .execute(connection)
+        .map_err(|e| Error::database("could not change npcs departure message", e))?;
+    match npcs {
+        0 => Err(Error::NotFound(format!("could not find npc: {}", name))),
+        1 => Ok(()),
+        _ => Err(Error::SchemaViolation(format!("{} npcs modified", npcs))),
+    }
+}
+
+pub fn change_instance_name(
+    connection: &SqliteConnection,
+    world: i32,
+    id: i32,
+    name: Option<String>,
+) -> Result<(), Error> {
+    let instances = diesel::update(npc_instances::table)
+        .filter(npc_instances::dsl::world.eq(world))
+        .filter(npc_instances::dsl::id.eq(id))
+        .set(npc_instances::dsl::name.eq(name))
+        .execute(connection)
+        .map_err(|e| Error::database("could not change instance name", e))?;
+    match instances {
+        0 => Err(Error::NotFound(format!("could not find instance: {}", id))),
+        1 => Ok(()),
+        _ => Err(Error::SchemaViolation(format!("{} instances modified", instances))),
+    }
+}
+
+pub fn change_instance_note(
+    connection: &SqliteConnection,
+    world: i32,
+    id: i32,
+    note: Option<String>,
+) -> Result<(), Error> {
+    let instances = diesel::update(npc_instances::table)
+        .filter(npc_instances::dsl::world.eq(world))
+        .filter(npc_instances::dsl::id.eq(id))
+        .set(npc_instances::dsl::notes.eq(note))
+        .execute(connection)
+        .map_err(|e| Error::database("could not change instance notes", e))?;
+    match instances {
+        0 => Err(Error::NotFound(format!("could not find instance: {}", id))),
+        1 => Ok(()),
+        _ => Err(Error::SchemaViolation(format!("{} instances modified", instances))),
+    }
+}
+
+/// Sets one attribute of an instance, `None` removes it.
+pub fn change_instance_attribute(
+    connection: &SqliteConnection,
+    world: i32,
+    id: i32,
+    key: String,
+    value: Option<String>,
+) -> Result<(), Error> {
+    let mut result: Vec<String> = npc_instances::table
+        .select(npc_instances::dsl::attributes)
+        .filter(npc_instances::dsl::world.eq(world))
+        .filter(npc_instances::dsl::id.eq(id))
+        .load(connection)
+        .map_err(|e| Error::database("could not query database for instance", e))?;
+    let mut attributes = match result.len() {
+        0 => return Err(Error::NotFound(format!("could not find instance: {}", id))),
+        1 => parse_attributes(&result.remove(0))?,
+        _ => {
+            return Err(Error::SchemaViolation(format!(
+                "{} instances with id {}",
+                result.len(),
+                id
+            )))
+        }
+    };
+    match value {
+        Some(value) => attributes.insert(key, value),
+        None => attributes.remove(&key),
+    };
+    let raw = serde_json::to_string(&attributes).map_err(|e| {
+        Error::Invalid(format!("could not serialize attributes: {}", e.to_string()))
+    })?;
+    diesel::update(npc_instances::table)
+        .filter(npc_instances::dsl::id.eq(id))
+        .set(npc_instances::dsl::attributes.eq(raw))
+        .execute(connection)
+        .map_err(|e| Error::database("could not change instance attributes", e))?;
+    Ok(())
+}
+
+pub fn parse_attributes(raw: &str) -> Result<BTreeMap<String, String>, Error> {
+    serde_json::from_str(raw).map_err(|e| {
+        Error::Database(format!(
+            "invalid database entry, could not deserialize attributes: {}",
             e.to_string()
-        )
+        ))
     })
 }
 
+pub fn remove_class(connection: &SqliteConnection, world: i32, name: String) -> Result<(), Error> {
+    let npcs = diesel::delete(npc_classes::table)
+        .filter(npc_classes::dsl::world.eq(world))
+        .filter(npc_classes::dsl::name.eq(name.clone()))
+        .execute(connection)
+        .map_err(|e| Error::database("could not delete npc", e))?;
+    match npcs {
+        0 => Err(Error::NotFound(format!("could not find npc: {}", name))),
+        1 => Ok(()),
+        _ => Err(Error::SchemaViolation(format!("{} npcs deleted", npcs))),
+    }
+}
+
+pub fn add_schedule(
+    connection: &SqliteConnection,
+    world: i32,
+    name: String,
+    weekdays: i32,
+    start_minute: i32,
+    end_minute: i32,
+) -> Result<(), Error> {
+    let class = get_class(connection, world, name)?;
+    let schedule = NewNpcSchedule {
+        class: class.id,
+        weekdays,
+        start_minute,
+        end_minute,
+    };
+    diesel::insert_into(npc_schedules::table)
+        .values(&schedule)
+        .execute(connection)
+        .map_err(|e| Error::database("could not insert schedule", e))?;
+    Ok(())
+}
+
+pub fn remove_schedule(connection: &SqliteConnection, world: i32, id: i32) -> Result<(), Error> {
+    let schedules = diesel::delete(npc_schedules::table)
+        .filter(npc_schedules::dsl::id.eq(id))
+        .filter(
+            npc_schedules::dsl::class.eq_any(
+                npc_classes::table
+                    .select(npc_classes::dsl::id)
+                    .filter(npc_classes::dsl::world.eq(world)),
+            ),
+        )
+        .execute(connection)
+        .map_err(|e| Error::database("could not delete schedule", e))?;
+    match schedules {
+        0 => Err(Error::NotFound(format!("could not find schedule: {}", id))),
+        1 => Ok(()),
+        _ => Err(Error::SchemaViolation(format!(
+            "{} schedules deleted",
+            schedules
+        ))),
+    }
+}
+
+/// Adds a whole `name` or a `syllable` to the name table of a class.
+pub fn add_name(
+    connection: &SqliteConnection,
+    world: i32,
+    class_name: String,
+    kind: &str,
+    value: String,
+) -> Result<(), Error> {
+    let class = get_class(connection, world, class_name)?;
+    let name = NewNpcName {
+        class: class.id,
+        kind,
+        value: &value,
+    };
+    diesel::insert_into(npc_names::table)
+        .values(&name)
+        .execute(connection)
+        .map_err(|e| Error::database(format!("could not insert {}", kind), e))?;
+    Ok(())
+}
+
+pub fn remove_name(
+    connection: &SqliteConnection,
+    world: i32,
+    class_name: String,
+    kind: &str,
+    value: String,
+) -> Result<(), Error> {
+    let class = get_class(connection, world, class_name)?;
+    let names = diesel::delete(npc_names::table)
+        .filter(npc_names::dsl::class.eq(class.id))
+        .filter(npc_names::dsl::kind.eq(kind))
+        .filter(npc_names::dsl::value.eq(value.clone()))
+        .execute(connection)
+        .map_err(|e| Error::database(format!("could not delete {}", kind), e))?;
+    match names {
+        0 => Err(Error::NotFound(format!("could not find {}: {}", kind, value))),
+        1 => Ok(()),
+        _ => Err(Error::SchemaViolation(format!("{} {}s deleted", names, kind))),
+    }
+}
+
+pub fn get_names(
+    connection: &SqliteConnection,
+    world: i32,
+    class_name: String,
+) -> Result<Vec<NpcName>, Error> {
+    let class = get_class(connection, world, class_name)?;
+    npc_names::table
+        .filter(npc_names::dsl::class.eq(class.id))
+        .order((npc_names::dsl::kind, npc_names::dsl::value))
+        .load(connection)
+        .map_err(|e| Error::database("could not query database for npc names", e))
+}
+
+pub fn get_schedules(
+    connection: &SqliteConnection,
+    world: i32,
+) -> Result<Vec<(NpcSchedule, NpcClass)>, Error> {
+    npc_schedules::table
+        .inner_join(npc_classes::table)
+        .filter(npc_classes::dsl::world.eq(world))
+        .load(connection)
+        .map_err(|e| Error::database("could not query database for npc schedules", e))
+}
+
+pub fn get_class(
+    connection: &SqliteConnection,
+    world: i32,
+    name: String,
+) -> Result<NpcClass, Error> {
+    let mut result: Vec<NpcClass> = npc_classes::table
+        .filter(npc_classes::dsl::world.eq(world))
+        .filter(npc_classes::dsl::name.eq(name.clone()))
+        .load(connection)
+        .map_err(|e| Error::database(format!("could not query database for npc {}", name), e))?;
+    match result.len() {
+        0 => Err(Error::NotFound(format!("could not find npc: {}", name))),
+        1 => Ok(result.remove(0)),
+        _ => Err(Error::SchemaViolation(format!(
+            "{} npcs named {}",
+            result.len(),
+            name
+        ))),
+    }
+}
+
+pub fn get_classes(connection: &SqliteConnection, world: i32) -> Result<Vec<NpcClass>, Error> {
+    npc_classes::table
+        .filter(npc_classes::dsl::world.eq(world))
+        .load(connection)
+        .map_err(|e| Error::database("could not query database for npc classes", e))
+}
+
 pub fn get_instances(
     connection: &SqliteConnection,
-) -> Result<Vec<(NpcInstance, NpcClass)>, String> {
+    world: i32,
+) -> Result<Vec<(NpcInstance, NpcClass)>, Error> {
     npc_instances::table
         .inner_join(npc_classes::table)
+        .filter(npc_instances::dsl::world.eq(world))
         .load(connection)
-        .map_err(|e| {
-            format!(
-                "could not query database for npc instances: {}",
-                e.to_string()
-            )
-        })
+        .map_err(|e| Error::database("could not query database for npc instances", e))
+}
+
+/// Events at or after the given game time, oldest first.
+pub fn get_events(
+    connection: &SqliteConnection,
+    world: i32,
+    since: NaiveDateTime,
+) -> Result<Vec<NpcEvent>, Error> {
+    npc_events::table
+        .filter(npc_events::dsl::world.eq(world))
+        .filter(npc_events::dsl::time.ge(since))
+        .order((npc_events::dsl::time, npc_events::dsl::id))
+        .load(connection)
+        .map_err(|e| Error::database("could not query database for npc events", e))
 }
 
-pub fn aliases_map(entries: Vec<Alias>) -> Result<HashMap<String, Vec<String>>, String> {
+/// The latest `count` events of a class, oldest first.
+pub fn get_class_events(
+    connection: &SqliteConnection,
+    world: i32,
+    class: i32,
+    count: i64,
+) -> Result<Vec<NpcEvent>, Error> {
+    let mut result: Vec<NpcEvent> = npc_events::table
+        .filter(npc_events::dsl::world.eq(world))
+        .filter(npc_events::dsl::class.eq(class))
+        .order((npc_events::dsl::time.desc(), npc_events::dsl::id.desc()))
+        .limit(count)
+        .load(connection)
+        .map_err(|e| Error::database("could not query database for npc events", e))?;
+    result.reverse();
+    Ok(result)
+}
+
+/// Events logged after the event with the given id, in the order they were logged.
+pub fn get_events_after(
+    connection: &SqliteConnection,
+    world: i32,
+    id: i32,
+) -> Result<Vec<NpcEvent>, Error> {
+    npc_events::table
+        .filter(npc_events::dsl::world.eq(world))
+        .filter(npc_events::dsl::id.gt(id))
+        .order(npc_events::dsl::id)
+        .load(connection)
+        .map_err(|e| Error::database("could not query database for npc events", e))
+}
+
+pub fn get_last_event(connection: &SqliteConnection, world: i32) -> Result<i32, Error> {
+    npc_events::table
+        .select(diesel::dsl::max(npc_events::dsl::id))
+        .filter(npc_events::dsl::world.eq(world))
+        .first::<Option<i32>>(connection)
+        .map(|id| id.unwrap_or(0))
+        .map_err(|e| Error::database("could not query database for npc events", e))
+}
+
+pub fn aliases_map(entries: Vec<Alias>) -> Result<HashMap<String, Vec<String>>, Error> {
     entries
         .into_iter()
         .map(|record| {
             let cmd = serde_json::from_str::<Vec<String>>(&record.command);
             match cmd {
                 Ok(command) => Ok((record.alias, command)),
-                Err(e) => Err(format!(
+                Err(e) => Err(Error::Database(format!(
                     "invalid database entry, could not deserialize command: {}",
                     e.to_string()
-                )),
+                ))),
             }
         })
         .collect()
 }
 
-pub fn get_aliases(connection: &SqliteConnection) -> Result<HashMap<String, Vec<String>>, String> {
+pub fn get_aliases(
+    connection: &SqliteConnection,
+    world: i32,
+) -> Result<HashMap<String, Vec<String>>, Error> {
     aliases::table
+        .filter(aliases::dsl::world.eq(world))
         .load(connection)
-        .map_err(|e| format!("could not query database for aliases: {}", e.to_string()))
+        .map_err(|e| Error::database("could not query database for aliases", e))
         .and_then(aliases_map)
 }
 
 pub fn get_constant(
     connection: &SqliteConnection,
+    world: i32,
     key: String,
-) -> Result<Option<Constant>, String> {
+) -> Result<Option<Constant>, Error> {
     let mut result: Vec<Constant> = constants::table
+        .filter(constants::dsl::world.eq(world))
         .filter(constants::dsl::key.eq(key.clone()))
         .load(connection)
         .map_err(|e| {
-            format!(
-                "could not query database for constant {}: {}",
-                key,
-                e.to_string()
-            )
+            Error::database(format!("could not query database for constant {}", key), e)
         })?;
 
     Ok(result.pop())
@@ -199,96 +590,498 @@ pub fn get_constant(
 
 pub fn add_alias(
     connection: &SqliteConnection,
+    world: i32,
     command: Vec<String>,
     alias: String,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     let serial: String = serde_json::to_string(&command)
-        .map_err(|e| format!("could not serialize command: {}", e.to_string()))?;
+        .map_err(|e| Error::Invalid(format!("could not serialize command: {}", e.to_string())))?;
     let alias = NewAlias {
         command: &serial,
         alias: &alias,
+        world,
     };
     diesel::insert_into(aliases::table)
         .values(&alias)
         .execute(connection)
-        .map_err(|e| format!("could not insert alias: {}", e.to_string()))?;
+        .map_err(|e| Error::database("could not insert alias", e))?;
     Ok(())
 }
 
-pub fn remove_alias(connection: &SqliteConnection, alias: String) -> Result<(), String> {
+pub fn remove_alias(connection: &SqliteConnection, world: i32, alias: String) -> Result<(), Error> {
     let result = diesel::delete(aliases::table)
+        .filter(aliases::dsl::world.eq(world))
         .filter(aliases::dsl::alias.eq(alias.clone()))
         .execute(connection)
-        .map_err(|e| format!("could not delete alias: {}", e.to_string()))?;
+        .map_err(|e| Error::database("could not delete alias", e))?;
     match result {
-        0 => Err(format!("could not find alias: {}", alias)),
+        0 => Err(Error::NotFound(format!("could not find alias: {}", alias))),
         1 => Ok(()),
-        _ => Err(format!("schema violation? {} aliases deleted", result)),
+        _ => Err(Error::SchemaViolation(format!(
+            "{} aliases deleted",
+            result
+        ))),
     }
 }
 
-pub fn is_admin(connection: &SqliteConnection, user_id: u64) -> Result<bool, String> {
-    let result: Vec<Admin> = admins::table
-        .filter(admins::dsl::user_id.eq(user_id.to_string()))
+pub fn get_permissions(connection: &SqliteConnection) -> Result<Vec<Permission>, Error> {
+    permissions::table
         .load(connection)
-        .map_err(|e| {
-            format!(
-                "could not query database for admin {}: {}",
-                user_id,
-                e.to_string()
-            )
-        })?;
+        .map_err(|e| Error::database("could not query database for permissions", e))
+}
 
-    match result.len() {
-        0 => Ok(false),
-        1 => Ok(true),
-        _ => Err(format!("schema violation? {} admins found", result.len())),
+/// Whether a permission counts in `guild`: grants made in a guild only count
+/// there, grants made without one (from the cli) count everywhere.
+pub fn applies_in(permission: &Permission, guild: Option<u64>) -> bool {
+    permission.guild.is_none() || permission.guild == guild.map(|g| g.to_string())
+}
+
+fn find_permission(
+    connection: &SqliteConnection,
+    guild: Option<u64>,
+    grantee: Grantee,
+) -> Result<Option<Permission>, Error> {
+    let guild = guild.map(|g| g.to_string());
+    let mut found = get_permissions(connection)?
+        .into_iter()
+        .filter(|p| p.kind == grantee.kind() && p.subject == grantee.subject() && p.guild == guild)
+        .collect::<Vec<_>>();
+    match found.len() {
+        0 | 1 => Ok(found.pop()),
+        n => Err(Error::SchemaViolation(format!(
+            "{} permissions found for {}",
+            n, grantee
+        ))),
     }
 }
 
+pub fn user_level(
+    connection: &SqliteConnection,
+    guild: Option<u64>,
+    user_id: u64,
+    roles: &[u64],
+) -> Result<Level, Error> {
+    let user = Grantee::User(user_id);
+    let roles = roles
+        .iter()
+        .map(|role| Grantee::Role(*role))
+        .collect::<Vec<_>>();
+    get_permissions(connection)?
+        .into_iter()
+        .filter(|p| applies_in(p, guild))
+        .filter(|p| {
+            (p.kind == user.kind() && p.subject == user.subject())
+                || roles
+                    .iter()
+                    .any(|role| p.kind == role.kind() && p.subject == role.subject())
+        })
+        .map(|p| Level::from_str(&p.level))
+        .collect::<Result<Vec<Level>, Error>>()
+        .map(|levels| levels.into_iter().fold(Level::Viewer, max))
+}
 
-pub fn add_admin(connection: &SqliteConnection, user_id: u64) -> Result<(), String> {
-    let uid_txt = user_id.to_string();
-    let admin = NewAdmin { user_id: &uid_txt };
-    diesel::insert_into(admins::table)
-        .values(&admin)
-        .execute(connection)
-        .map_err(|e| format!("could not insert admin: {}", e.to_string()))?;
-    Ok(())
+pub fn grant_permission(
+    connection: &SqliteConnection,
+    guild: Option<u64>,
+    grantee: Grantee,
+    level: Level,
+) -> Result<(), Error> {
+    let level_txt = level.to_string();
+    let existing = find_permission(connection, guild, grantee)?;
+    let updated = match existing {
+        Some(permission) => diesel::update(permissions::table)
+            .filter(permissions::dsl::id.eq(permission.id))
+            .set(permissions::dsl::level.eq(level_txt.clone()))
+            .execute(connection)
+            .map_err(|e| Error::database("could not change permission", e))?,
+        None => 0,
+    };
+    match updated {
+        0 => {
+            let subject = grantee.subject();
+            let guild_txt = guild.map(|g| g.to_string());
+            let permission = NewPermission {
+                kind: grantee.kind(),
+                subject: &subject,
+                level: &level_txt,
+                guild: guild_txt.as_ref().map(String::as_ref),
+            };
+            diesel::insert_into(permissions::table)
+                .values(&permission)
+                .execute(connection)
+                .map_err(|e| Error::database("could not insert permission", e))?;
+            Ok(())
+        }
+        1 => Ok(()),
+        _ => Err(Error::SchemaViolation(format!(
+            "{} permissions modified",
+            updated
+        ))),
+    }
 }
 
-pub fn remove_admin(connection: &SqliteConnection, user_id: u64) -> Result<(), String> {
-    let uid_txt = user_id.to_string();
-    let result = diesel::delete(admins::table)
-        .filter(admins::dsl::user_id.eq(uid_txt))
+pub fn revoke_permission(
+    connection: &SqliteConnection,
+    guild: Option<u64>,
+    grantee: Grantee,
+) -> Result<(), Error> {
+    let permission = find_permission(connection, guild, grantee)?.ok_or_else(|| {
+        Error::NotFound(format!("could not find permission for {}", grantee))
+    })?;
+    let result = diesel::delete(permissions::table)
+        .filter(permissions::dsl::id.eq(permission.id))
         .execute(connection)
-        .map_err(|e| format!("could not delete admin: {}", e.to_string()))?;
+        .map_err(|e| Error::database("could not delete permission", e))?;
     match result {
-        0 => Err(format!("could not find admin: {}", user_id)),
+        0 => Err(Error::NotFound(format!(
+            "could not find permission for {}",
+            grantee
+        ))),
         1 => Ok(()),
-        _ => Err(format!("schema violation? {} admins deleted", result)),
+        _ => Err(Error::SchemaViolation(format!(
+            "{} permissions deleted",
+            result
+        ))),
     }
 }
 
-pub fn change_constant(
+pub fn set_constant(
     connection: &SqliteConnection,
+    world: i32,
     key: String,
     value: String,
-) -> Result<(), String> {
-    let result = diesel::update(constants::table)
+) -> Result<(), Error> {
+    let updated = diesel::update(constants::table)
+        .filter(constants::dsl::world.eq(world))
         .filter(constants::dsl::key.eq(key.clone()))
-        .set(constants::dsl::value.eq(value))
+        .set(constants::dsl::value.eq(value.clone()))
+        .execute(connection)
+        .map_err(|e| Error::database(format!("could not change value of {} constant", key), e))?;
+    match updated {
+        0 => {
+            let constant = NewConstant {
+                key: &key,
+                value: &value,
+                world,
+            };
+            diesel::insert_into(constants::table)
+                .values(&constant)
+                .execute(connection)
+                .map_err(|e| Error::database(format!("could not insert {} constant", key), e))?;
+            Ok(())
+        }
+        1 => Ok(()),
+        _ => Err(Error::SchemaViolation(format!(
+            "multiple \"{}\" constants",
+            key
+        ))),
+    }
+}
+
+pub fn get_worlds(connection: &SqliteConnection) -> Result<Vec<World>, Error> {
+    worlds::table
+        .load(connection)
+        .map_err(|e| Error::database("could not query database for worlds", e))
+}
+
+pub fn get_world(connection: &SqliteConnection, name: String) -> Result<World, Error> {
+    let mut result: Vec<World> = worlds::table
+        .filter(worlds::dsl::name.eq(name.clone()))
+        .load(connection)
+        .map_err(|e| Error::database(format!("could not query database for world {}", name), e))?;
+    match result.len() {
+        0 => Err(Error::NotFound(format!("could not find world: {}", name))),
+        1 => Ok(result.remove(0)),
+        _ => Err(Error::SchemaViolation(format!(
+            "{} worlds named {}",
+            result.len(),
+            name
+        ))),
+    }
+}
+
+pub fn add_world(connection: &SqliteConnection, name: String) -> Result<(), Error> {
+    let world = NewWorld { name: &name };
+    diesel::insert_into(worlds::table)
+        .values(&world)
+        .execute(connection)
+        .map_err(|e| Error::database("could not insert world", e))?;
+    Ok(())
+}
+
+pub fn remove_world(connection: &SqliteConnection, name: String) -> Result<(), Error> {
+    if name == DEFAULT_WORLD {
+        return Err(Error::Invalid(format!(
+            "the {} world can not be removed",
+            DEFAULT_WORLD
+        )));
+    }
+    let world = get_world(connection, name)?;
+    // classes, instances, schedules and everything else in the world cascade
+    diesel::update(guild_settings::table)
+        .filter(guild_settings::dsl::world.eq(world.id))
+        .set(guild_settings::dsl::world.eq(None::<i32>))
+        .execute(connection)
+        .map_err(|e| Error::database("could not unset guild worlds", e))?;
+    diesel::delete(worlds::table)
+        .filter(worlds::dsl::id.eq(world.id))
+        .execute(connection)
+        .map_err(|e| Error::database("could not delete world", e))?;
+    Ok(())
+}
+
+pub fn get_bindings(connection: &SqliteConnection) -> Result<Vec<(WorldBinding, World)>, Error> {
+    world_bindings::table
+        .inner_join(worlds::table)
+        .load(connection)
+        .map_err(|e| Error::database("could not query database for world bindings", e))
+}
+
+pub fn bind_world(
+    connection: &SqliteConnection,
+    channel_id: u64,
+    name: String,
+) -> Result<(), Error> {
+    let world = get_world(connection, name)?;
+    let channel_txt = channel_id.to_string();
+    let updated = diesel::update(world_bindings::table)
+        .filter(world_bindings::dsl::channel_id.eq(channel_txt.clone()))
+        .set(world_bindings::dsl::world.eq(world.id))
         .execute(connection)
+        .map_err(|e| Error::database("could not change world binding", e))?;
+    match updated {
+        0 => {
+            let binding = NewWorldBinding {
+                channel_id: &channel_txt,
+                world: world.id,
+            };
+            diesel::insert_into(world_bindings::table)
+                .values(&binding)
+                .execute(connection)
+                .map_err(|e| Error::database("could not insert world binding", e))?;
+            Ok(())
+        }
+        1 => Ok(()),
+        _ => Err(Error::SchemaViolation(format!(
+            "{} bindings modified",
+            updated
+        ))),
+    }
+}
+
+pub fn unbind_world(connection: &SqliteConnection, channel_id: u64) -> Result<(), Error> {
+    let result = diesel::delete(world_bindings::table)
+        .filter(world_bindings::dsl::channel_id.eq(channel_id.to_string()))
+        .execute(connection)
+        .map_err(|e| Error::database("could not delete world binding", e))?;
+    match result {
+        0 => Err(Error::NotFound(format!(
+            "could not find binding for channel: {}",
+            channel_id
+        ))),
+        1 => Ok(()),
+        _ => Err(Error::SchemaViolation(format!(
+            "{} bindings deleted",
+            result
+        ))),
+    }
+}
+
+pub fn resolve_world(
+    connection: &SqliteConnection,
+    channel_id: Option<u64>,
+    guild_id: Option<u64>,
+) -> Result<World, Error> {
+    if let Some(channel_id) = channel_id {
+        let mut result: Vec<(WorldBinding, World)> = world_bindings::table
+            .inner_join(worlds::table)
+            .filter(world_bindings::dsl::channel_id.eq(channel_id.to_string()))
+            .load(connection)
+            .map_err(|e| Error::database("could not query database for world binding", e))?;
+        if let Some((_binding, world)) = result.pop() {
+            return Ok(world);
+        }
+    }
+    if let Some(guild_id) = guild_id {
+        let default = get_guild_settings(connection, guild_id)?.and_then(|s| s.world);
+        if let Some(world) = default {
+            return worlds::table
+                .filter(worlds::dsl::id.eq(world))
+                .first(connection)
+                .map_err(|e| {
+                    Error::database(format!("could not query database for world {}", world), e)
+                });
+        }
+    }
+    get_world(connection, DEFAULT_WORLD.to_string())
+}
+
+pub fn get_guild_settings(
+    connection: &SqliteConnection,
+    guild_id: u64,
+) -> Result<Option<GuildSettings>, Error> {
+    let mut result: Vec<GuildSettings> = guild_settings::table
+        .filter(guild_settings::dsl::guild_id.eq(guild_id.to_string()))
+        .load(connection)
         .map_err(|e| {
-            format!(
-                "could not change value of {} constant: {}",
-                key,
-                e.to_string()
+            Error::database(
+                format!("could not query database for guild {} settings", guild_id),
+                e,
             )
         })?;
-    match result {
-        0 => Err(format!("constant not found: {}", key)),
-        1 => Ok(()),
-        _ => Err(format!("schema violation? multiple \"{}\" constants", key)),
+
+    Ok(result.pop())
+}
+
+fn ensure_guild_settings(connection: &SqliteConnection, guild_id: u64) -> Result<(), Error> {
+    if get_guild_settings(connection, guild_id)?.is_none() {
+        let gid_txt = guild_id.to_string();
+        let settings = NewGuildSettings { guild_id: &gid_txt };
+        diesel::insert_into(guild_settings::table)
+            .values(&settings)
+            .execute(connection)
+            .map_err(|e| Error::database("could not insert guild settings", e))?;
+    }
+    Ok(())
+}
+
+pub fn change_guild_prefix(
+    connection: &SqliteConnection,
+    guild_id: u64,
+    prefix: Option<String>,
+) -> Result<(), Error> {
+    ensure_guild_settings(connection, guild_id)?;
+    diesel::update(guild_settings::table)
+        .filter(guild_settings::dsl::guild_id.eq(guild_id.to_string()))
+        .set(guild_settings::dsl::prefix.eq(prefix))
+        .execute(connection)
+        .map_err(|e| Error::database("could not change guild prefix", e))?;
+    Ok(())
+}
+
+pub fn change_guild_role(
+    connection: &SqliteConnection,
+    guild_id: u64,
+    role_id: Option<u64>,
+) -> Result<(), Error> {
+    ensure_guild_settings(connection, guild_id)?;
+    diesel::update(guild_settings::table)
+        .filter(guild_settings::dsl::guild_id.eq(guild_id.to_string()))
+        .set(guild_settings::dsl::admin_role.eq(role_id.map(|id| id.to_string())))
+        .execute(connection)
+        .map_err(|e| Error::database("could not change guild admin role", e))?;
+    Ok(())
+}
+
+pub fn change_guild_world(
+    connection: &SqliteConnection,
+    guild_id: u64,
+    name: Option<String>,
+) -> Result<(), Error> {
+    let world = match name {
+        Some(name) => Some(get_world(connection, name)?.id),
+        None => None,
+    };
+    ensure_guild_settings(connection, guild_id)?;
+    diesel::update(guild_settings::table)
+        .filter(guild_settings::dsl::guild_id.eq(guild_id.to_string()))
+        .set(guild_settings::dsl::world.eq(world))
+        .execute(connection)
+        .map_err(|e| Error::database("could not change guild world", e))?;
+    Ok(())
+}
+
+pub fn change_guild_channel(
+    connection: &SqliteConnection,
+    guild_id: u64,
+    channel_id: Option<u64>,
+) -> Result<(), Error> {
+    ensure_guild_settings(connection, guild_id)?;
+    diesel::update(guild_settings::table)
+        .filter(guild_settings::dsl::guild_id.eq(guild_id.to_string()))
+        .set(guild_settings::dsl::output_channel.eq(channel_id.map(|id| id.to_string())))
+        .execute(connection)
+        .map_err(|e| Error::database("could not change guild output channel", e))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel::connection::SimpleConnection;
+    use test_connection;
+
+    #[test]
+    fn guild_grants_only_count_in_their_guild() {
+        let connection = test_connection();
+        grant_permission(&connection, Some(1), Grantee::User(10), Level::Owner).unwrap();
+        grant_permission(&connection, None, Grantee::Role(20), Level::Gm).unwrap();
+
+        assert_eq!(user_level(&connection, Some(1), 10, &[]).unwrap(), Level::Owner);
+        assert_eq!(user_level(&connection, Some(2), 10, &[]).unwrap(), Level::Viewer);
+        assert_eq!(user_level(&connection, None, 10, &[]).unwrap(), Level::Viewer);
+        assert_eq!(user_level(&connection, Some(2), 10, &[20]).unwrap(), Level::Gm);
+    }
+
+    #[test]
+    fn grants_are_changed_and_revoked_per_guild() {
+        let connection = test_connection();
+        grant_permission(&connection, Some(1), Grantee::User(10), Level::Gm).unwrap();
+        grant_permission(&connection, Some(2), Grantee::User(10), Level::Gm).unwrap();
+        grant_permission(&connection, Some(1), Grantee::User(10), Level::Owner).unwrap();
+        assert_eq!(user_level(&connection, Some(1), 10, &[]).unwrap(), Level::Owner);
+        assert_eq!(user_level(&connection, Some(2), 10, &[]).unwrap(), Level::Gm);
+
+        assert!(revoke_permission(&connection, None, Grantee::User(10)).is_err());
+        revoke_permission(&connection, Some(2), Grantee::User(10)).unwrap();
+        assert_eq!(user_level(&connection, Some(2), 10, &[]).unwrap(), Level::Viewer);
+        assert_eq!(get_permissions(&connection).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn attributes_are_a_json_object_of_strings() {
+        let attributes = parse_attributes(r#"{"mood":"grumpy","hp":"12"}"#).unwrap();
+        assert_eq!(attributes.get("mood").map(String::as_str), Some("grumpy"));
+        assert_eq!(attributes.get("hp").map(String::as_str), Some("12"));
+        assert!(parse_attributes("{}").unwrap().is_empty());
+        assert!(parse_attributes(r#"{"hp":12}"#).is_err());
+        assert!(parse_attributes("grumpy").is_err());
+    }
+
+    #[test]
+    fn instance_attributes_are_set_replaced_and_removed() {
+        let connection = test_connection();
+        connection
+            .batch_execute(
+                "INSERT INTO `npc_classes` (`name`, `commonality`) VALUES ('guard', 10); \
+                 INSERT INTO `npc_instances` (`class`) VALUES (1);",
+            )
+            .unwrap();
+        let attributes = |connection: &SqliteConnection| -> String {
+            npc_instances::table
+                .select(npc_instances::dsl::attributes)
+                .first(connection)
+                .unwrap()
+        };
+        let set = |key: &str, value: Option<&str>| {
+            change_instance_attribute(&connection, 1, 1, key.to_string(), value.map(str::to_owned))
+        };
+
+        set("mood", Some("grumpy")).unwrap();
+        set("hp", Some("12")).unwrap();
+        set("mood", Some("sleepy")).unwrap();
+        assert_eq!(attributes(&connection), r#"{"hp":"12","mood":"sleepy"}"#);
+        set("hp", None).unwrap();
+        set("gold", None).unwrap();
+        assert_eq!(attributes(&connection), r#"{"mood":"sleepy"}"#);
+
+        match change_instance_attribute(&connection, 2, 1, "hp".to_string(), None) {
+            Err(Error::NotFound(_)) => {}
+            other => panic!("expected the instance to be missing, got {:?}", other),
+        }
+        match change_instance_attribute(&connection, 1, 2, "hp".to_string(), None) {
+            Err(Error::NotFound(_)) => {}
+            other => panic!("expected the instance to be missing, got {:?}", other),
+        }
     }
 }
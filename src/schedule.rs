@@ -0,0 +1,170 @@
+use chrono::Datelike;
+use chrono::NaiveDateTime;
+use chrono::Timelike;
+use error::Error;
+use models::NpcSchedule;
+use std::str::FromStr;
+
+pub const ALL_WEEKDAYS: i32 = 0b111_1111;
+pub const MINUTES_PER_DAY: i32 = 24 * 60;
+
+const WEEKDAY_NAMES: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// Schedules are in game time, like every time the bot shows or parses. The
+/// game clock starts out equal to UTC and only moves with `change clock`, so a
+/// GM who wants local opening hours sets the clock to local time once.
+pub fn is_open(schedules: &[NpcSchedule], time: &NaiveDateTime) -> bool {
+    if schedules.is_empty() {
+        return true;
+    }
+    let weekday = time.weekday().num_days_from_monday() as i32;
+    let yesterday = (weekday + 6) % 7;
+    let minute = (time.hour() * 60 + time.minute()) as i32;
+    schedules.iter().any(|schedule| {
+        let on = |day: i32| schedule.weekdays & (1 << day) != 0;
+        if schedule.start_minute < schedule.end_minute {
+            on(weekday) && schedule.start_minute <= minute && minute < schedule.end_minute
+        } else if schedule.start_minute > schedule.end_minute {
+            (on(weekday) && schedule.start_minute <= minute)
+                || (on(yesterday) && minute < schedule.end_minute)
+        } else {
+            on(weekday)
+        }
+    })
+}
+
+fn parse_weekday(day: &str) -> Result<i32, Error> {
+    WEEKDAY_NAMES
+        .iter()
+        .position(|name| day.to_lowercase().starts_with(name))
+        .map(|position| position as i32)
+        .ok_or_else(|| Error::Parse(format!("invalid weekday: {}", day)))
+}
+
+pub fn parse_weekdays(days: &str) -> Result<i32, Error> {
+    match days.to_lowercase().as_ref() {
+        "all" | "daily" => return Ok(ALL_WEEKDAYS),
+        _ => {}
+    }
+    days.split(',')
+        .map(|part| {
+            let mut bounds = part.splitn(2, '-');
+            let first = parse_weekday(bounds.next().unwrap_or(""))?;
+            let last = match bounds.next() {
+                Some(last) => parse_weekday(last)?,
+                None => first,
+            };
+            let mut mask = 0;
+            let mut day = first;
+            loop {
+                mask |= 1 << day;
+                if day == last {
+                    break;
+                }
+                day = (day + 1) % 7;
+            }
+            Ok(mask)
+        })
+        .collect::<Result<Vec<i32>, Error>>()
+        .map(|masks| masks.into_iter().fold(0, |acc, mask| acc | mask))
+}
+
+pub fn format_weekdays(weekdays: i32) -> String {
+    if weekdays & ALL_WEEKDAYS == ALL_WEEKDAYS {
+        return "daily".to_string();
+    }
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for day in 0..7 {
+        if weekdays & (1 << day) == 0 {
+            continue;
+        }
+        let extends = match runs.last() {
+            Some(&(_, last)) => last + 1 == day,
+            None => false,
+        };
+        if extends {
+            runs.last_mut().map(|run| run.1 = day);
+        } else {
+            runs.push((day, day));
+        }
+    }
+    runs.into_iter()
+        .map(|(first, last)| {
+            if first == last {
+                WEEKDAY_NAMES[first].to_string()
+            } else {
+                format!("{}-{}", WEEKDAY_NAMES[first], WEEKDAY_NAMES[last])
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Parses a game time of day into minutes since game midnight, no timezone applies.
+pub fn parse_time(time: &str) -> Result<i32, Error> {
+    let mut parts = time.splitn(2, ':');
+    let hours = parts.next().map(i32::from_str);
+    let minutes = parts.next().map(i32::from_str).unwrap_or(Ok(0));
+    match (hours, minutes) {
+        (Some(Ok(hours)), Ok(minutes))
+            if hours >= 0 && minutes >= 0 && minutes < 60
+                && hours * 60 + minutes <= MINUTES_PER_DAY =>
+        {
+            Ok(hours * 60 + minutes)
+        }
+        _ => Err(Error::Parse(format!("invalid time (HH:MM): {}", time))),
+    }
+}
+
+pub fn format_time(minute: i32) -> String {
+    format!("{:02}:{:02}", minute / 60, minute % 60)
+}
+
+pub fn format_schedule(schedule: &NpcSchedule) -> String {
+    format!(
+        "{} {}-{}",
+        format_weekdays(schedule.weekdays),
+        format_time(schedule.start_minute),
+        format_time(schedule.end_minute)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn schedule(days: &str, from: &str, to: &str) -> NpcSchedule {
+        NpcSchedule {
+            id: 1,
+            class: 1,
+            weekdays: parse_weekdays(days).unwrap(),
+            start_minute: parse_time(from).unwrap(),
+            end_minute: parse_time(to).unwrap(),
+        }
+    }
+
+    #[test]
+    fn opening_hours_are_read_on_the_game_clock() {
+        let schedules = [schedule("mon-fri", "09:00", "17:00")];
+        // 2018-09-03 is a monday
+        let monday = NaiveDate::from_ymd(2018, 9, 3);
+        assert!(!is_open(&schedules, &monday.and_hms(8, 59, 59)));
+        assert!(is_open(&schedules, &monday.and_hms(9, 0, 0)));
+        assert!(!is_open(&schedules, &monday.and_hms(17, 0, 0)));
+        let saturday = NaiveDate::from_ymd(2018, 9, 8);
+        assert!(!is_open(&schedules, &saturday.and_hms(12, 0, 0)));
+        assert_eq!(format_schedule(&schedules[0]), "mon-fri 09:00-17:00");
+    }
+
+    #[test]
+    fn overnight_hours_continue_into_the_next_day() {
+        let schedules = [schedule("fri", "22:00", "02:00")];
+        let friday = NaiveDate::from_ymd(2018, 9, 7);
+        let saturday = NaiveDate::from_ymd(2018, 9, 8);
+        assert!(is_open(&schedules, &friday.and_hms(23, 0, 0)));
+        assert!(is_open(&schedules, &saturday.and_hms(1, 59, 0)));
+        assert!(!is_open(&schedules, &saturday.and_hms(2, 0, 0)));
+        assert!(!is_open(&schedules, &friday.and_hms(1, 0, 0)));
+    }
+}
@@ -1,15 +1,9 @@
-table! {
-    admins (id) {
-        id -> Integer,
-        user_id -> Text,
-    }
-}
-
 table! {
     aliases (id) {
         id -> Integer,
         alias -> Text,
         command -> Text,
+        world -> Integer,
     }
 }
 
@@ -18,6 +12,18 @@ table! {
         id -> Integer,
         key -> Text,
         value -> Text,
+        world -> Integer,
+    }
+}
+
+table! {
+    guild_settings (id) {
+        id -> Integer,
+        guild_id -> Text,
+        prefix -> Nullable<Text>,
+        admin_role -> Nullable<Text>,
+        world -> Nullable<Integer>,
+        output_channel -> Nullable<Text>,
     }
 }
 
@@ -29,6 +35,23 @@ table! {
         next_tick -> Timestamp,
         active -> Integer,
         unique -> Integer,
+        visit_minutes -> Integer,
+        world -> Integer,
+        announce -> Integer,
+        arrival_message -> Nullable<Text>,
+        departure_message -> Nullable<Text>,
+    }
+}
+
+table! {
+    npc_events (id) {
+        id -> Integer,
+        world -> Integer,
+        kind -> Text,
+        class -> Integer,
+        class_name -> Text,
+        instance -> Integer,
+        time -> Timestamp,
     }
 }
 
@@ -37,9 +60,77 @@ table! {
         id -> Integer,
         class -> Integer,
         active_until -> Timestamp,
+        world -> Integer,
+        name -> Nullable<Text>,
+        notes -> Nullable<Text>,
+        attributes -> Text,
+    }
+}
+
+table! {
+    npc_names (id) {
+        id -> Integer,
+        class -> Integer,
+        kind -> Text,
+        value -> Text,
+    }
+}
+
+table! {
+    npc_schedules (id) {
+        id -> Integer,
+        class -> Integer,
+        weekdays -> Integer,
+        start_minute -> Integer,
+        end_minute -> Integer,
+    }
+}
+
+table! {
+    permissions (id) {
+        id -> Integer,
+        kind -> Text,
+        subject -> Text,
+        level -> Text,
+        guild -> Nullable<Text>,
+    }
+}
+
+table! {
+    world_bindings (id) {
+        id -> Integer,
+        channel_id -> Text,
+        world -> Integer,
+    }
+}
+
+table! {
+    worlds (id) {
+        id -> Integer,
+        name -> Text,
     }
 }
 
+joinable!(aliases -> worlds (world));
+joinable!(constants -> worlds (world));
+joinable!(guild_settings -> worlds (world));
+joinable!(npc_classes -> worlds (world));
+joinable!(npc_events -> worlds (world));
 joinable!(npc_instances -> npc_classes (class));
+joinable!(npc_names -> npc_classes (class));
+joinable!(npc_schedules -> npc_classes (class));
+joinable!(world_bindings -> worlds (world));
 
-allow_tables_to_appear_in_same_query!(admins, aliases, constants, npc_classes, npc_instances,);
+allow_tables_to_appear_in_same_query!(
+    aliases,
+    constants,
+    guild_settings,
+    npc_classes,
+    npc_events,
+    npc_instances,
+    npc_names,
+    npc_schedules,
+    permissions,
+    world_bindings,
+    worlds,
+);
@@ -0,0 +1,216 @@
+use diesel::sqlite::SqliteConnection;
+use error::Error;
+use permissions::Level;
+use queries::get_constant;
+use queries::set_constant;
+use std::str::FromStr;
+use timing::change_tick;
+use timing::DEFAULT_TICK_SECONDS;
+
+/// A world constant that can be changed with `change setting`. Internal state
+/// such as the clock constants is kept out of the registry.
+pub struct Setting {
+    pub key: &'static str,
+    pub description: &'static str,
+    pub level: Level,
+    default: fn() -> Option<String>,
+    validate: fn(&str) -> Result<(), Error>,
+    apply: fn(&SqliteConnection, i32, &str) -> Result<(), Error>,
+}
+
+impl Setting {
+    pub fn default(&self) -> Option<String> {
+        (self.default)()
+    }
+}
+
+pub const SETTINGS: &[Setting] = &[
+    Setting {
+        key: "starter",
+        description: "command prefix in this world's channels, the bot prefix when unset",
+        level: Level::Owner,
+        default: no_default,
+        validate: validate_starter,
+        apply: store_starter,
+    },
+    Setting {
+        key: "tick_seconds",
+        description: "seconds between npc spawn rolls",
+        level: Level::Gm,
+        default: default_tick,
+        validate: validate_tick,
+        apply: apply_tick,
+    },
+    Setting {
+        key: "announce_channel",
+        description: "channel for npc arrivals and departures, none to turn them off",
+        level: Level::Owner,
+        default: no_default,
+        validate: validate_channel,
+        apply: store_channel,
+    },
+    Setting {
+        key: "arrival_message",
+        description: "announcement when an npc arrives, {class} is replaced with its name",
+        level: Level::Gm,
+        default: default_arrival,
+        validate: validate_message,
+        apply: store_arrival,
+    },
+    Setting {
+        key: "departure_message",
+        description: "announcement when an npc leaves, {class} is replaced with its name",
+        level: Level::Gm,
+        default: default_departure,
+        validate: validate_message,
+        apply: store_departure,
+    },
+];
+
+pub fn find_setting(key: &str) -> Result<&'static Setting, Error> {
+    SETTINGS.iter().find(|s| s.key == key).ok_or_else(|| {
+        Error::Parse(format!(
+            "unknown setting: {} ({})",
+            key,
+            SETTINGS
+                .iter()
+                .map(|s| s.key)
+                .collect::<Vec<_>>()
+                .join(" | ")
+        ))
+    })
+}
+
+/// The stored value, or the registry default when the constant was never set.
+pub fn get_setting(
+    connection: &SqliteConnection,
+    world: i32,
+    key: &str,
+) -> Result<Option<String>, Error> {
+    let setting = find_setting(key)?;
+    Ok(get_constant(connection, world, key.to_string())?
+        .map(|c| c.value)
+        .or_else(|| setting.default()))
+}
+
+/// Returns every setting with its stored value, `None` when it is unset.
+pub fn get_settings(
+    connection: &SqliteConnection,
+    world: i32,
+) -> Result<Vec<(&'static Setting, Option<String>)>, Error> {
+    SETTINGS
+        .iter()
+        .map(|setting| {
+            get_constant(connection, world, setting.key.to_string())
+                .map(|constant| (setting, constant.map(|c| c.value)))
+        })
+        .collect()
+}
+
+/// Validates the value against the registry, then inserts or updates it.
+pub fn change_setting(
+    connection: &SqliteConnection,
+    world: i32,
+    key: &str,
+    value: &str,
+) -> Result<(), Error> {
+    let setting = find_setting(key)?;
+    (setting.validate)(value)?;
+    (setting.apply)(connection, world, value)
+}
+
+fn no_default() -> Option<String> {
+    None
+}
+
+fn validate_starter(value: &str) -> Result<(), Error> {
+    if value.is_empty() || value.contains(char::is_whitespace) {
+        return Err(Error::Invalid(
+            "starter must be non-empty and without whitespace".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn store_starter(connection: &SqliteConnection, world: i32, value: &str) -> Result<(), Error> {
+    set_constant(connection, world, "starter".to_string(), value.to_string())
+}
+
+fn default_tick() -> Option<String> {
+    Some(DEFAULT_TICK_SECONDS.to_string())
+}
+
+fn validate_tick(value: &str) -> Result<(), Error> {
+    match i64::from_str(value) {
+        Ok(seconds) if seconds > 0 => Ok(()),
+        _ => Err(Error::Invalid(format!(
+            "invalid tick length: {} (positive number of seconds)",
+            value
+        ))),
+    }
+}
+
+// changing the tick also realigns every class to the new tick boundaries
+fn apply_tick(connection: &SqliteConnection, world: i32, value: &str) -> Result<(), Error> {
+    let seconds =
+        i64::from_str(value).map_err(|e| Error::Invalid(format!("invalid tick length: {}", e)))?;
+    change_tick(connection, world, seconds)
+}
+
+fn validate_channel(value: &str) -> Result<(), Error> {
+    parse_channel(value).map(|_| ())
+}
+
+// stores the bare id, so `<#123>` and `123` end up the same
+fn store_channel(connection: &SqliteConnection, world: i32, value: &str) -> Result<(), Error> {
+    let value = parse_channel(value)?.map_or_else(|| "none".to_string(), |id| id.to_string());
+    set_constant(connection, world, "announce_channel".to_string(), value)
+}
+
+/// Parses a channel mention or id, `none` turns announcements off.
+pub fn parse_channel(value: &str) -> Result<Option<u64>, Error> {
+    if value == "none" {
+        return Ok(None);
+    }
+    let id = if value.starts_with("<#") && value.ends_with('>') {
+        &value[2..value.len() - 1]
+    } else {
+        value
+    };
+    u64::from_str(id)
+        .map(Some)
+        .map_err(|_| Error::Invalid(format!("invalid channel: {} (mention | none)", value)))
+}
+
+fn default_arrival() -> Option<String> {
+    Some("The {class} has arrived".to_string())
+}
+
+fn default_departure() -> Option<String> {
+    Some("The {class} has left".to_string())
+}
+
+fn validate_message(value: &str) -> Result<(), Error> {
+    if value.trim().is_empty() {
+        return Err(Error::Invalid("message can not be empty".to_string()));
+    }
+    Ok(())
+}
+
+fn store_arrival(connection: &SqliteConnection, world: i32, value: &str) -> Result<(), Error> {
+    set_constant(
+        connection,
+        world,
+        "arrival_message".to_string(),
+        value.to_string(),
+    )
+}
+
+fn store_departure(connection: &SqliteConnection, world: i32, value: &str) -> Result<(), Error> {
+    set_constant(
+        connection,
+        world,
+        "departure_message".to_string(),
+        value.to_string(),
+    )
+}
@@ -0,0 +1,468 @@
+use clock;
+use diesel;
+use diesel::sqlite::SqliteConnection;
+use diesel::Connection;
+use diesel::ExpressionMethods;
+use diesel::QueryDsl;
+use diesel::RunQueryDsl;
+use error::Error;
+use models::Alias;
+use models::Constant;
+use models::NewAlias;
+use models::NewConstant;
+use models::NewNpcClass;
+use models::NewNpcInstance;
+use models::NewNpcName;
+use models::NewNpcSchedule;
+use models::NewPermission;
+use models::NpcClass;
+use models::NpcInstance;
+use models::NpcName;
+use models::NpcSchedule;
+use models::Permission;
+use models::World;
+use schema::{
+    aliases, constants, npc_classes, npc_instances, npc_names, npc_schedules, permissions,
+};
+use serde_json;
+use std::collections::HashMap;
+use std::str::FromStr;
+use timing::check_rate;
+use timing::get_tick;
+use toml;
+
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum SnapshotFormat {
+    Json,
+    Toml,
+}
+
+impl SnapshotFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            &SnapshotFormat::Json => "json",
+            &SnapshotFormat::Toml => "toml",
+        }
+    }
+}
+
+impl FromStr for SnapshotFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<SnapshotFormat, Error> {
+        match s {
+            "json" => Ok(SnapshotFormat::Json),
+            "toml" => Ok(SnapshotFormat::Toml),
+            other => Err(Error::Parse(format!(
+                "invalid snapshot format: {} (json | toml)",
+                other
+            ))),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ImportMode {
+    Merge,
+    Replace,
+}
+
+impl FromStr for ImportMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<ImportMode, Error> {
+        match s {
+            "merge" => Ok(ImportMode::Merge),
+            "replace" => Ok(ImportMode::Replace),
+            other => Err(Error::Parse(format!(
+                "invalid import mode: {} (merge | replace)",
+                other
+            ))),
+        }
+    }
+}
+
+/// Everything needed to recreate a world. Schedules and instances refer to
+/// classes by the ids stored in `classes`, which are remapped on import.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Snapshot {
+    pub version: u32,
+    pub world: String,
+    pub classes: Vec<NpcClass>,
+    #[serde(default)]
+    pub schedules: Vec<NpcSchedule>,
+    #[serde(default)]
+    pub names: Vec<NpcName>,
+    #[serde(default)]
+    pub aliases: Vec<Alias>,
+    #[serde(default)]
+    pub constants: Vec<Constant>,
+    #[serde(default)]
+    pub permissions: Vec<Permission>,
+    #[serde(default)]
+    pub instances: Vec<NpcInstance>,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct ImportReport {
+    pub classes: usize,
+    pub schedules: usize,
+    pub names: usize,
+    pub aliases: usize,
+    pub constants: usize,
+    pub permissions: usize,
+    pub instances: usize,
+    pub conflicts: Vec<String>,
+}
+
+pub fn export_world(
+    connection: &SqliteConnection,
+    world: &World,
+    with_instances: bool,
+) -> Result<Snapshot, Error> {
+    let classes: Vec<NpcClass> = npc_classes::table
+        .filter(npc_classes::dsl::world.eq(world.id))
+        .order(npc_classes::dsl::id)
+        .load(connection)
+        .map_err(|e| Error::database("could not query database for npc classes", e))?;
+    let schedules: Vec<NpcSchedule> = npc_schedules::table
+        .inner_join(npc_classes::table)
+        .filter(npc_classes::dsl::world.eq(world.id))
+        .select(npc_schedules::all_columns)
+        .order(npc_schedules::dsl::id)
+        .load(connection)
+        .map_err(|e| Error::database("could not query database for npc schedules", e))?;
+    let names: Vec<NpcName> = npc_names::table
+        .inner_join(npc_classes::table)
+        .filter(npc_classes::dsl::world.eq(world.id))
+        .select(npc_names::all_columns)
+        .order(npc_names::dsl::id)
+        .load(connection)
+        .map_err(|e| Error::database("could not query database for npc names", e))?;
+    let aliases: Vec<Alias> = aliases::table
+        .filter(aliases::dsl::world.eq(world.id))
+        .order(aliases::dsl::alias)
+        .load(connection)
+        .map_err(|e| Error::database("could not query database for aliases", e))?;
+    let constants: Vec<Constant> = constants::table
+        .filter(constants::dsl::world.eq(world.id))
+        .order(constants::dsl::key)
+        .load(connection)
+        .map_err(|e| Error::database("could not query database for constants", e))?;
+    let permissions: Vec<Permission> = permissions::table
+        .filter(permissions::dsl::guild.is_null())
+        .order((permissions::dsl::kind, permissions::dsl::subject))
+        .load(connection)
+        .map_err(|e| Error::database("could not query database for permissions", e))?;
+    let instances: Vec<NpcInstance> = if with_instances {
+        npc_instances::table
+            .filter(npc_instances::dsl::world.eq(world.id))
+            .order(npc_instances::dsl::id)
+            .load(connection)
+            .map_err(|e| Error::database("could not query database for npc instances", e))?
+    } else {
+        Vec::new()
+    };
+    Ok(Snapshot {
+        version: SNAPSHOT_VERSION,
+        world: world.name.clone(),
+        classes,
+        schedules,
+        names,
+        aliases,
+        constants,
+        permissions,
+        instances,
+    })
+}
+
+pub fn to_document(snapshot: &Snapshot, format: SnapshotFormat) -> Result<String, Error> {
+    match format {
+        SnapshotFormat::Json => serde_json::to_string_pretty(snapshot)
+            .map_err(|e| Error::Invalid(format!("could not serialize snapshot: {}", e))),
+        // going through a toml::Value puts plain values before the tables
+        SnapshotFormat::Toml => toml::Value::try_from(snapshot)
+            .and_then(|value| toml::to_string(&value))
+            .map_err(|e| Error::Invalid(format!("could not serialize snapshot: {}", e))),
+    }
+}
+
+pub fn from_document(document: &str) -> Result<Snapshot, Error> {
+    let snapshot: Snapshot = if document.trim().starts_with('{') {
+        serde_json::from_str(document)
+            .map_err(|e| Error::Parse(format!("invalid json snapshot: {}", e)))?
+    } else {
+        toml::from_str(document)
+            .map_err(|e| Error::Parse(format!("invalid toml snapshot: {}", e)))?
+    };
+    if snapshot.version != SNAPSHOT_VERSION {
+        return Err(Error::Invalid(format!(
+            "unsupported snapshot version: {} (expected {})",
+            snapshot.version, SNAPSHOT_VERSION
+        )));
+    }
+    Ok(snapshot)
+}
+
+/// Loads a snapshot into `world` in a single transaction. Replace clears the
+/// world (and the permissions granted outside of any guild) first, merge skips
+/// and reports entries whose names are already taken, together with everything
+/// attached to them.
+pub fn import_world(
+    connection: &SqliteConnection,
+    world: i32,
+    snapshot: Snapshot,
+    mode: ImportMode,
+) -> Result<ImportReport, Error> {
+    let mut failure = None;
+    let mut report = ImportReport::default();
+    connection
+        .transaction::<_, diesel::result::Error, _>(|| {
+            import_entries(connection, world, &snapshot, mode, &mut report).map_err(|e| {
+                failure = Some(e);
+                diesel::result::Error::RollbackTransaction
+            })
+        })
+        .map_err(|e| failure.unwrap_or_else(|| Error::database("could not import snapshot", e)))?;
+    Ok(report)
+}
+
+fn import_entries(
+    connection: &SqliteConnection,
+    world: i32,
+    snapshot: &Snapshot,
+    mode: ImportMode,
+    report: &mut ImportReport,
+) -> Result<(), Error> {
+    if mode == ImportMode::Replace {
+        clear_world(connection, world)?;
+    }
+
+    // constants go first so new classes start ticking at the imported clock time
+    let mut existing_constants: Vec<String> = constants::table
+        .select(constants::dsl::key)
+        .filter(constants::dsl::world.eq(world))
+        .load(connection)
+        .map_err(|e| Error::database("could not query database for constants", e))?;
+    for constant in &snapshot.constants {
+        if existing_constants.contains(&constant.key) {
+            report
+                .conflicts
+                .push(format!("constant {} already exists", constant.key));
+            continue;
+        }
+        let new_constant = NewConstant {
+            key: &constant.key,
+            value: &constant.value,
+            world,
+        };
+        diesel::insert_into(constants::table)
+            .values(&new_constant)
+            .execute(connection)
+            .map_err(|e| {
+                Error::database(format!("could not insert {} constant", constant.key), e)
+            })?;
+        existing_constants.push(constant.key.clone());
+        report.constants += 1;
+    }
+
+    let mut existing_classes: Vec<String> = npc_classes::table
+        .select(npc_classes::dsl::name)
+        .filter(npc_classes::dsl::world.eq(world))
+        .load(connection)
+        .map_err(|e| Error::database("could not query database for npc classes", e))?;
+    let time = clock::now(connection, world)?;
+    let tick = get_tick(connection, world)?;
+    let mut class_ids: HashMap<i32, i32> = HashMap::new();
+    for class in &snapshot.classes {
+        if existing_classes.contains(&class.name) {
+            report
+                .conflicts
+                .push(format!("class {} already exists", class.name));
+            continue;
+        }
+        if class.visit_minutes <= 0 {
+            report
+                .conflicts
+                .push(format!("class {}: visit time must be positive", class.name));
+            continue;
+        }
+        if let Err(e) = check_rate(class.commonality, class.visit_minutes, &tick) {
+            report.conflicts.push(format!("class {}: {}", class.name, e));
+            continue;
+        }
+        let new_class = NewNpcClass {
+            name: &class.name,
+            commonality: class.commonality,
+            next_tick: time,
+            active: class.active,
+            unique: class.unique,
+            visit_minutes: class.visit_minutes,
+            world,
+            announce: class.announce,
+            arrival_message: class.arrival_message.as_ref().map(String::as_ref),
+            departure_message: class.departure_message.as_ref().map(String::as_ref),
+        };
+        diesel::insert_into(npc_classes::table)
+            .values(&new_class)
+            .execute(connection)
+            .map_err(|e| Error::database(format!("could not insert npc {}", class.name), e))?;
+        let id = npc_classes::table
+            .select(npc_classes::dsl::id)
+            .filter(npc_classes::dsl::world.eq(world))
+            .filter(npc_classes::dsl::name.eq(&class.name))
+            .first(connection)
+            .map_err(|e| {
+                Error::database(
+                    format!("could not query database for npc {}", class.name),
+                    e,
+                )
+            })?;
+        class_ids.insert(class.id, id);
+        existing_classes.push(class.name.clone());
+        report.classes += 1;
+    }
+
+    for schedule in &snapshot.schedules {
+        if let Some(&class) = class_ids.get(&schedule.class) {
+            let new_schedule = NewNpcSchedule {
+                class,
+                weekdays: schedule.weekdays,
+                start_minute: schedule.start_minute,
+                end_minute: schedule.end_minute,
+            };
+            diesel::insert_into(npc_schedules::table)
+                .values(&new_schedule)
+                .execute(connection)
+                .map_err(|e| Error::database("could not insert schedule", e))?;
+            report.schedules += 1;
+        }
+    }
+
+    for name in &snapshot.names {
+        if let Some(&class) = class_ids.get(&name.class) {
+            let new_name = NewNpcName {
+                class,
+                kind: &name.kind,
+                value: &name.value,
+            };
+            diesel::insert_into(npc_names::table)
+                .values(&new_name)
+                .execute(connection)
+                .map_err(|e| Error::database("could not insert npc name", e))?;
+            report.names += 1;
+        }
+    }
+
+    for instance in &snapshot.instances {
+        if let Some(&class) = class_ids.get(&instance.class) {
+            let new_instance = NewNpcInstance {
+                class,
+                active_until: instance.active_until,
+                world,
+                name: instance.name.clone(),
+                notes: instance.notes.clone(),
+                attributes: instance.attributes.clone(),
+            };
+            diesel::insert_into(npc_instances::table)
+                .values(&new_instance)
+                .execute(connection)
+                .map_err(|e| Error::database("could not insert npc instance", e))?;
+            report.instances += 1;
+        }
+    }
+
+    let mut existing_aliases: Vec<String> = aliases::table
+        .select(aliases::dsl::alias)
+        .filter(aliases::dsl::world.eq(world))
+        .load(connection)
+        .map_err(|e| Error::database("could not query database for aliases", e))?;
+    for alias in &snapshot.aliases {
+        if existing_aliases.contains(&alias.alias) {
+            report
+                .conflicts
+                .push(format!("alias {} already exists", alias.alias));
+            continue;
+        }
+        let new_alias = NewAlias {
+            alias: &alias.alias,
+            command: &alias.command,
+            world,
+        };
+        diesel::insert_into(aliases::table)
+            .values(&new_alias)
+            .execute(connection)
+            .map_err(|e| Error::database(format!("could not insert alias {}", alias.alias), e))?;
+        existing_aliases.push(alias.alias.clone());
+        report.aliases += 1;
+    }
+
+    // permissions are global, so they are only replaced by a snapshot that has some
+    if mode == ImportMode::Replace && !snapshot.permissions.is_empty() {
+        diesel::delete(permissions::table)
+            .filter(permissions::dsl::guild.is_null())
+            .execute(connection)
+            .map_err(|e| Error::database("could not delete permissions", e))?;
+    }
+    let mut existing_permissions: Vec<(String, String)> = permissions::table
+        .select((permissions::dsl::kind, permissions::dsl::subject))
+        .filter(permissions::dsl::guild.is_null())
+        .load(connection)
+        .map_err(|e| Error::database("could not query database for permissions", e))?;
+    for permission in &snapshot.permissions {
+        let key = (permission.kind.clone(), permission.subject.clone());
+        if existing_permissions.contains(&key) {
+            report.conflicts.push(format!(
+                "permission for {} {} already exists",
+                permission.kind, permission.subject
+            ));
+            continue;
+        }
+        let new_permission = NewPermission {
+            kind: &permission.kind,
+            subject: &permission.subject,
+            level: &permission.level,
+            guild: None,
+        };
+        diesel::insert_into(permissions::table)
+            .values(&new_permission)
+            .execute(connection)
+            .map_err(|e| Error::database("could not insert permission", e))?;
+        existing_permissions.push(key);
+        report.permissions += 1;
+    }
+    Ok(())
+}
+
+fn clear_world(connection: &SqliteConnection, world: i32) -> Result<(), Error> {
+    let classes = npc_classes::table
+        .select(npc_classes::dsl::id)
+        .filter(npc_classes::dsl::world.eq(world));
+    diesel::delete(npc_schedules::table)
+        .filter(npc_schedules::dsl::class.eq_any(classes))
+        .execute(connection)
+        .map_err(|e| Error::database("could not delete world schedules", e))?;
+    diesel::delete(npc_names::table)
+        .filter(npc_names::dsl::class.eq_any(classes))
+        .execute(connection)
+        .map_err(|e| Error::database("could not delete world npc names", e))?;
+    diesel::delete(npc_instances::table)
+        .filter(npc_instances::dsl::world.eq(world))
+        .execute(connection)
+        .map_err(|e| Error::database("could not delete world instances", e))?;
+    diesel::delete(npc_classes::table)
+        .filter(npc_classes::dsl::world.eq(world))
+        .execute(connection)
+        .map_err(|e| Error::database("could not delete world npcs", e))?;
+    diesel::delete(aliases::table)
+        .filter(aliases::dsl::world.eq(world))
+        .execute(connection)
+        .map_err(|e| Error::database("could not delete world aliases", e))?;
+    diesel::delete(constants::table)
+        .filter(constants::dsl::world.eq(world))
+        .execute(connection)
+        .map_err(|e| Error::database("could not delete world constants", e))?;
+    Ok(())
+}
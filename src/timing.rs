@@ -1,24 +1,40 @@
 use byteorder;
 use byteorder::ByteOrder;
+use clock::jump;
+use clock::GameClock;
 use chrono;
 use chrono::Datelike;
 use chrono::Duration;
 use chrono::NaiveDateTime;
 use chrono::Timelike;
-use chrono::Utc;
 use diesel;
 use diesel::sqlite::SqliteConnection;
+use diesel::Connection;
 use diesel::ExpressionMethods;
 use diesel::QueryDsl;
 use diesel::RunQueryDsl;
+use error::Error;
+use models::no_attributes;
+use models::NewNpcEvent;
 use models::NewNpcInstance;
 use models::NpcClass;
+use models::NpcEvent;
 use models::NpcInstance;
+use models::NpcName;
+use models::NpcSchedule;
+use queries::get_constant;
+use queries::set_constant;
 use rand::distributions::{Bernoulli, Distribution};
 use rand::ChaChaRng;
+use rand::Rng;
 use rand::SeedableRng;
-use schema::{npc_classes, npc_instances};
-use std::mem;
+use schedule::is_open;
+use schema::{npc_classes, npc_events, npc_instances, npc_names, npc_schedules};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+pub const DEFAULT_TICK_SECONDS: i64 = 10;
+pub const MAX_TICK_SECONDS: i64 = 24 * 60 * 60;
 
 #[rustfmt::skip]
 fn seed(dt: &NaiveDateTime, id: i32) -> [u8; 32] {
@@ -39,145 +55,792 @@ fn seed(dt: &NaiveDateTime, id: i32) -> [u8; 32] {
     ]
 }
 
-pub fn fast_forward_instances(
-    connection: &SqliteConnection,
-    shift: chrono::Duration,
-) -> Result<(), String> {
-    let time = Utc::now().naive_utc();
-    let forward_time: NaiveDateTime = time + shift;
-    let tick = Duration::seconds(10);
-    let classes = npc_classes::table
-        .filter(npc_classes::dsl::active.gt(0))
+pub fn get_tick(connection: &SqliteConnection, world: i32) -> Result<Duration, Error> {
+    match get_constant(connection, world, "tick_seconds".to_string())? {
+        None => Ok(Duration::seconds(DEFAULT_TICK_SECONDS)),
+        Some(constant) => i64::from_str(&constant.value)
+            .map(Duration::seconds)
+            .map_err(|e| Error::Invalid(format!("invalid tick_seconds constant: {}", e))),
+    }
+}
+
+/// One roll per tick can keep at most visit / tick npcs present on average,
+/// so a frequency (percent of one npc present) above that would be capped.
+pub fn check_rate(commonality: i32, visit_minutes: i32, tick: &Duration) -> Result<(), Error> {
+    let visit_seconds = i64::from(visit_minutes.max(1)) * 60;
+    let max = visit_seconds * 100 / tick.num_seconds().max(1);
+    if commonality < 0 || i64::from(commonality) > max {
+        return Err(Error::Invalid(format!(
+            "frequency {} out of range 0-{} for a {} minute visit and a {} second tick",
+            commonality,
+            max,
+            visit_minutes,
+            tick.num_seconds()
+        )));
+    }
+    Ok(())
+}
+
+pub fn change_tick(connection: &SqliteConnection, world: i32, seconds: i64) -> Result<(), Error> {
+    if seconds <= 0 || seconds > MAX_TICK_SECONDS {
+        return Err(Error::Invalid(format!(
+            "tick length must be from 1 to {} seconds",
+            MAX_TICK_SECONDS
+        )));
+    }
+    let classes: Vec<NpcClass> = npc_classes::table
+        .filter(npc_classes::dsl::world.eq(world))
         .load(connection)
-        .map_err(|e| {
-            format!(
-                "could not query database for npc classes: {}",
-                e.to_string()
+        .map_err(|e| Error::database("could not query database for npc classes", e))?;
+    for class in &classes {
+        check_rate(
+            class.commonality,
+            class.visit_minutes,
+            &Duration::seconds(seconds),
+        )
+        .map_err(|e| Error::Invalid(format!("{}: {}", class.name, e)))?;
+    }
+    let mut failure = None;
+    connection
+        .transaction::<_, diesel::result::Error, _>(|| {
+            set_constant(
+                connection,
+                world,
+                "tick_seconds".to_string(),
+                seconds.to_string(),
             )
-        })?;
-
-    for mut class in classes {
-        let instances = create_instances(&mut class, &forward_time, &tick, &Duration::minutes(20));
+            .map_err(|e| {
+                failure = Some(e);
+                diesel::result::Error::RollbackTransaction
+            })?;
+            for class in &classes {
+                diesel::update(npc_classes::table)
+                    .filter(npc_classes::dsl::id.eq(class.id))
+                    .set(npc_classes::dsl::next_tick.eq(align_tick(&class.next_tick, seconds)))
+                    .execute(connection)?;
+            }
+            Ok(())
+        })
+        .map_err(|e| failure.unwrap_or_else(|| Error::database("could not change tick length", e)))
+}
 
-        diesel::insert_into(npc_instances::table)
-            .values(&instances)
-            .execute(connection)
-            .map_err(|e| format!("could not insert npc instances: {}", e))?;
+fn align_tick(time: &NaiveDateTime, seconds: i64) -> NaiveDateTime {
+    let mut timestamp = time.timestamp();
+    if time.timestamp_subsec_nanos() > 0 {
+        timestamp += 1;
+    }
+    let remainder = timestamp % seconds;
+    if remainder > 0 {
+        timestamp += seconds - remainder;
+    }
+    NaiveDateTime::from_timestamp(timestamp, 0)
+}
 
-        diesel::update(npc_classes::table)
-            .filter(npc_classes::dsl::id.eq(class.id))
-            .set(npc_classes::dsl::next_tick.eq(class.next_tick - shift))
-            .execute(connection)
-            .map_err(|e| format!("could not update npc next generation: {}", e))?;
+fn get_schedules(
+    connection: &SqliteConnection,
+    world: i32,
+) -> Result<HashMap<i32, Vec<NpcSchedule>>, Error> {
+    let schedules: Vec<NpcSchedule> = npc_schedules::table
+        .inner_join(npc_classes::table)
+        .filter(npc_classes::dsl::world.eq(world))
+        .select(npc_schedules::all_columns)
+        .load(connection)
+        .map_err(|e| Error::database("could not query database for npc schedules", e))?;
+    let mut result: HashMap<i32, Vec<NpcSchedule>> = HashMap::new();
+    for schedule in schedules {
+        result.entry(schedule.class).or_insert_with(Vec::new).push(schedule);
     }
+    Ok(result)
+}
 
-    for mut instance in npc_instances::table
-        .load::<NpcInstance>(connection)
-        .map_err(|e| format!("could not collect npc instances: {}", e))?
-    {
-        diesel::update(npc_instances::table)
-            .filter(npc_instances::dsl::id.eq(instance.id))
-            .set(npc_instances::dsl::active_until.eq(instance.active_until - shift))
-            .execute(connection)
-            .map_err(|e| format!("could not shift npc expiration date: {}", e))?;
+// ordered by value, so the generated names do not depend on insertion order
+fn get_names(
+    connection: &SqliteConnection,
+    world: i32,
+) -> Result<HashMap<i32, Vec<NpcName>>, Error> {
+    let names: Vec<NpcName> = npc_names::table
+        .inner_join(npc_classes::table)
+        .filter(npc_classes::dsl::world.eq(world))
+        .select(npc_names::all_columns)
+        .order((npc_names::dsl::kind, npc_names::dsl::value))
+        .load(connection)
+        .map_err(|e| Error::database("could not query database for npc names", e))?;
+    let mut result: HashMap<i32, Vec<NpcName>> = HashMap::new();
+    for name in names {
+        result.entry(name.class).or_insert_with(Vec::new).push(name);
     }
+    Ok(result)
+}
 
-    diesel::delete(npc_instances::table)
-        .filter(npc_instances::dsl::active_until.lt(time))
-        .execute(connection)
-        .map_err(|e| format!("could not remove old npc instances: {}", e))?;
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct InstanceChange {
+    pub instance: i32,
+    pub class: i32,
+    pub class_name: String,
+    pub time: NaiveDateTime,
+}
 
-    Ok(())
+/// What an update changed. Spawns are timed at the tick that rolled them,
+/// expirations when the visit ended and collapses at the time of the update.
+/// Visits that ended before the update are never created, so an instance is
+/// not both spawned and expired by the same update.
+#[derive(Serialize, Debug, Default)]
+pub struct UpdateReport {
+    pub spawned: Vec<InstanceChange>,
+    pub expired: Vec<InstanceChange>,
+    pub collapsed: Vec<InstanceChange>,
 }
 
-pub fn update_instances(connection: &SqliteConnection) -> Result<(), String> {
-    let time: NaiveDateTime = Utc::now().naive_utc();
-    let tick = Duration::seconds(10);
-    let classes = npc_classes::table
+pub fn fast_forward_instances(
+    connection: &SqliteConnection,
+    world: i32,
+    shift: chrono::Duration,
+) -> Result<UpdateReport, Error> {
+    jump(connection, world, shift)?;
+    update_instances(connection, world)
+}
+
+/// Spawns and removes instances up to the current game time, records every
+/// change in the event log and returns them.
+pub fn update_instances(connection: &SqliteConnection, world: i32) -> Result<UpdateReport, Error> {
+    let mut report = UpdateReport::default();
+    let clock = GameClock::load(connection, world)?;
+    if clock.paused {
+        return Ok(report);
+    }
+    let time: NaiveDateTime = clock.now()?;
+    let tick = get_tick(connection, world)?;
+    let classes: Vec<NpcClass> = npc_classes::table
+        .filter(npc_classes::dsl::world.eq(world))
         .filter(npc_classes::dsl::active.gt(0))
         .load(connection)
-        .map_err(|e| {
-            format!(
-                "could not query database for npc classes: {}",
-                e.to_string()
-            )
-        })?;
+        .map_err(|e| Error::database("could not query database for npc classes", e))?;
+    let mut schedules = get_schedules(connection, world)?;
+    let mut names = get_names(connection, world)?;
+    // ids only grow, so everything above the current maximum was spawned below
+    let last_id = npc_instances::table
+        .select(diesel::dsl::max(npc_instances::dsl::id))
+        .first::<Option<i32>>(connection)
+        .map_err(|e| Error::database("could not query database for npc instances", e))?
+        .unwrap_or(0);
 
     for mut class in classes {
-        let instances = create_instances(&mut class, &time, &tick, &Duration::minutes(20));
+        let class_schedules = schedules.remove(&class.id).unwrap_or_default();
+        let class_names = names.remove(&class.id).unwrap_or_default();
+        let instances =
+            create_instances(&mut class, &class_schedules, &class_names, &time, &tick);
 
         diesel::insert_into(npc_instances::table)
             .values(&instances)
             .execute(connection)
-            .map_err(|e| format!("could not insert npc instances: {}", e))?;
+            .map_err(|e| Error::database("could not insert npc instances", e))?;
 
         diesel::update(npc_classes::table)
             .filter(npc_classes::dsl::id.eq(class.id))
             .set(npc_classes::dsl::next_tick.eq(class.next_tick))
             .execute(connection)
-            .map_err(|e| format!("could not update npc next generation: {}", e))?;
+            .map_err(|e| Error::database("could not update npc next generation", e))?;
     }
 
+    let spawned: Vec<(NpcInstance, NpcClass)> = npc_instances::table
+        .inner_join(npc_classes::table)
+        .filter(npc_instances::dsl::world.eq(world))
+        .filter(npc_instances::dsl::id.gt(last_id))
+        .order(npc_instances::dsl::id)
+        .load(connection)
+        .map_err(|e| Error::database("could not query database for new npc instances", e))?;
+    report.spawned = spawned
+        .into_iter()
+        .map(|(instance, class)| {
+            let arrival = instance.active_until - Duration::minutes(class.visit_minutes as i64);
+            change(&instance, class, arrival)
+        })
+        .collect();
+
+    let expired: Vec<(NpcInstance, NpcClass)> = npc_instances::table
+        .inner_join(npc_classes::table)
+        .filter(npc_instances::dsl::world.eq(world))
+        .filter(npc_instances::dsl::active_until.lt(time))
+        .order(npc_instances::dsl::id)
+        .load(connection)
+        .map_err(|e| Error::database("could not query database for old npc instances", e))?;
     diesel::delete(npc_instances::table)
+        .filter(npc_instances::dsl::world.eq(world))
         .filter(npc_instances::dsl::active_until.lt(time))
         .execute(connection)
-        .map_err(|e| format!("could not remove old npc instances: {}", e))?;
+        .map_err(|e| Error::database("could not remove old npc instances", e))?;
+    report.expired = expired
+        .into_iter()
+        .map(|(instance, class)| {
+            let departure = instance.active_until;
+            change(&instance, class, departure)
+        })
+        .collect();
 
     let unique: Vec<NpcClass> = npc_classes::table
+        .filter(npc_classes::dsl::world.eq(world))
         .filter(npc_classes::dsl::unique.eq(1))
         .load(connection)
-        .map_err(|e| {
-            format!(
-                "could not query database for unique classes: {}",
-                e.to_string()
-            )
-        })?;
+        .map_err(|e| Error::database("could not query database for unique classes", e))?;
 
     for class in unique {
-        if let Ok(first) = npc_instances::table
+        // the visit that lasts longest stays, the others are collapsed into it
+        let mut instances: Vec<NpcInstance> = npc_instances::table
+            .filter(npc_instances::dsl::class.eq(class.id))
+            .order((
+                npc_instances::dsl::active_until.desc(),
+                npc_instances::dsl::id.desc(),
+            ))
+            .load(connection)
+            .map_err(|e| Error::database("could not query database for unique npc instances", e))?;
+        if instances.len() < 2 {
+            continue;
+        }
+        let first = instances.remove(0);
+        diesel::delete(npc_instances::table)
             .filter(npc_instances::dsl::class.eq(class.id))
-            .order((npc_instances::dsl::active_until.desc(), npc_instances::dsl::id.desc()))
-            .first::<NpcInstance>(connection) {
-                diesel::delete(npc_instances::table)
-                    .filter(npc_instances::dsl::class.eq(class.id))
-                    .filter(npc_instances::dsl::id.ne(first.id))
-                    .execute(connection)
-                    .map_err(|e| format!("could not remove older unique npc instances: {}", e))?;
+            .filter(npc_instances::dsl::id.ne(first.id))
+            .execute(connection)
+            .map_err(|e| Error::database("could not remove older unique npc instances", e))?;
+        for instance in instances {
+            report.collapsed.push(InstanceChange {
+                instance: instance.id,
+                class: class.id,
+                class_name: class.name.clone(),
+                time,
+            });
         }
     }
 
+    log_changes(connection, world, "spawned", &report.spawned)?;
+    log_changes(connection, world, "expired", &report.expired)?;
+    log_changes(connection, world, "collapsed", &report.collapsed)?;
+    Ok(report)
+}
+
+/// Removes one instance, or every instance of the world when `id` is `None`,
+/// and logs them as removed at the current game time.
+pub fn remove_instances(
+    connection: &SqliteConnection,
+    world: i32,
+    id: Option<i32>,
+) -> Result<usize, Error> {
+    let time = GameClock::load(connection, world)?.now()?;
+    let mut query = npc_instances::table
+        .inner_join(npc_classes::table)
+        .filter(npc_instances::dsl::world.eq(world))
+        .order(npc_instances::dsl::id)
+        .into_boxed();
+    if let Some(id) = id {
+        query = query.filter(npc_instances::dsl::id.eq(id));
+    }
+    let instances: Vec<(NpcInstance, NpcClass)> = query
+        .load(connection)
+        .map_err(|e| Error::database("could not query database for npc instances", e))?;
+    if let (Some(id), true) = (id, instances.is_empty()) {
+        return Err(Error::NotFound(format!("could not find instance: {}", id)));
+    }
+    let ids = instances.iter().map(|(instance, _)| instance.id).collect::<Vec<_>>();
+    diesel::delete(npc_instances::table)
+        .filter(npc_instances::dsl::id.eq_any(&ids))
+        .execute(connection)
+        .map_err(|e| Error::database("could not remove instances", e))?;
+    let removed = instances
+        .into_iter()
+        .map(|(instance, class)| change(&instance, class, time))
+        .collect::<Vec<_>>();
+    log_changes(connection, world, "removed", &removed)?;
+    Ok(removed.len())
+}
+
+fn change(instance: &NpcInstance, class: NpcClass, time: NaiveDateTime) -> InstanceChange {
+    InstanceChange {
+        instance: instance.id,
+        class: class.id,
+        class_name: class.name,
+        time,
+    }
+}
+
+fn log_changes(
+    connection: &SqliteConnection,
+    world: i32,
+    kind: &str,
+    changes: &[InstanceChange],
+) -> Result<(), Error> {
+    let events = changes
+        .iter()
+        .map(|change| NewNpcEvent {
+            world,
+            kind,
+            class: change.class,
+            class_name: &change.class_name,
+            instance: change.instance,
+            time: change.time,
+        })
+        .collect::<Vec<_>>();
+    diesel::insert_into(npc_events::table)
+        .values(&events)
+        .execute(connection)
+        .map_err(|e| Error::database(format!("could not log {} npc instances", kind), e))?;
     Ok(())
 }
 
+/// Whether a spawn is the npc arriving. Overlapping visits of a unique npc are
+/// collapsed into the one lasting longest at the next update, which may be an
+/// older or a newer visit. Only the visit that began first is an arrival, the
+/// others continue its stay. A visit without a spawn in `events` began before them.
+pub fn arrives(events: &[NpcEvent], spawn: &NpcEvent) -> bool {
+    let began_after = |instance: i32| {
+        events.iter().any(|event| {
+            event.kind == "spawned" && event.instance == instance && event.time > spawn.time
+        })
+    };
+    // the instance of the class still present after a collapse at `time`
+    let survivor = |time: NaiveDateTime| {
+        events
+            .iter()
+            .filter(|event| {
+                event.kind == "spawned"
+                    && event.class == spawn.class
+                    && event.instance != spawn.instance
+                    && event.time <= time
+            })
+            .map(|event| event.instance)
+            .find(|&instance| {
+                events
+                    .iter()
+                    .filter(|event| event.instance == instance && event.kind != "spawned")
+                    .all(|event| event.time > time)
+            })
+    };
+    let collapsed = events
+        .iter()
+        .find(|event| event.kind == "collapsed" && event.instance == spawn.instance);
+    if let Some(collapse) = collapsed {
+        if !survivor(collapse.time).map_or(false, &began_after) {
+            return false;
+        }
+    }
+    // a visit collapsed after this one began was already there when it did
+    !events.iter().any(|event| {
+        event.kind == "collapsed"
+            && event.class == spawn.class
+            && event.instance != spawn.instance
+            && event.time >= spawn.time
+            && !began_after(event.instance)
+    })
+}
+
 pub fn create_instances(
     class: &mut NpcClass,
+    schedules: &[NpcSchedule],
+    names: &[NpcName],
     final_time: &NaiveDateTime,
     tick: &chrono::Duration,
-    visit_time: &chrono::Duration,
 ) -> Vec<NewNpcInstance> {
     let mut result = Vec::new();
+    let visit_time = Duration::minutes(class.visit_minutes.max(1) as i64);
+    // each spawn stays for visit_time, so spawning with probability
+    // tick / visit_time * commonality keeps on average commonality% of one npc present
     let probability = ((tick.num_seconds() as f64) / (visit_time.num_seconds() as f64))
         * ((class.commonality as f64) / 100f64);
-    let distribution = Bernoulli::new(probability);
-    let mut last_tick = None;
+    let distribution = Bernoulli::new(probability.max(0f64).min(1f64));
+
+    // instances spawned before the final visit_time window are dropped by the retain below,
+    // and every tick seeds its own rng, so after a long pause those ticks can be skipped
+    let tick_seconds = tick.num_seconds();
+    if &class.next_tick <= final_time && tick_seconds > 0 {
+        let ticks = (*final_time - class.next_tick).num_seconds() / tick_seconds;
+        let skipped = ticks - visit_time.num_seconds() / tick_seconds;
+        if skipped > 0 {
+            class.next_tick = class.next_tick + Duration::seconds(skipped * tick_seconds);
+        }
+    }
 
     while &class.next_tick <= final_time {
         let mut rng = ChaChaRng::from_seed(seed(&class.next_tick, class.id));
         (0..10).for_each(|_| {
             distribution.sample(&mut rng);
         });
-        if distribution.sample(&mut rng) {
+        if distribution.sample(&mut rng) && is_open(schedules, &class.next_tick) {
             result.push(NewNpcInstance {
                 class: class.id,
-                active_until: class.next_tick + *visit_time,
+                active_until: class.next_tick + visit_time,
+                world: class.world,
+                // drawn after the spawn roll, so names leave the spawns themselves unchanged
+                name: generate_name(&class.name, names, &mut rng),
+                notes: None,
+                attributes: no_attributes(),
             });
         }
-        let next_tick = class.next_tick + *tick;
-        last_tick = Some(mem::replace(&mut class.next_tick, next_tick));
+        class.next_tick = class.next_tick + *tick;
     }
 
-    if let Some(last_tick) = last_tick {
-        result.retain(|v| v.active_until >= last_tick);
-    }
+    // visits that are over by the final time are never created
+    result.retain(|v| &v.active_until >= final_time);
     result
 }
+
+/// Picks a name from the class name table with the rng of the spawning tick,
+/// so the guard becomes "Guard Aldric". Whole names are preferred, otherwise
+/// two or three syllables are joined.
+fn generate_name(class: &str, names: &[NpcName], rng: &mut ChaChaRng) -> Option<String> {
+    let whole = names.iter().filter(|n| n.kind == "name").collect::<Vec<_>>();
+    let syllables = names
+        .iter()
+        .filter(|n| n.kind == "syllable")
+        .collect::<Vec<_>>();
+    let name = if !whole.is_empty() {
+        whole[pick(rng, whole.len())].value.clone()
+    } else if !syllables.is_empty() {
+        let count = 2 + pick(rng, 2);
+        let joined = (0..count)
+            .map(|_| syllables[pick(rng, syllables.len())].value.as_str())
+            .collect::<String>();
+        capitalize(&joined)
+    } else {
+        return None;
+    };
+    Some(format!("{} {}", capitalize(class), name))
+}
+
+// u32 draws only, ChaChaRng reads a u64 misaligned after an odd number of u32 draws
+fn pick(rng: &mut ChaChaRng, len: usize) -> usize {
+    rng.gen_range(0, len as u32) as usize
+}
+
+fn capitalize(value: &str) -> String {
+    let mut chars = value.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use diesel::connection::SimpleConnection;
+    use schedule::ALL_WEEKDAYS;
+    use test_connection;
+
+    fn class(commonality: i32, visit_minutes: i32, next_tick: NaiveDateTime) -> NpcClass {
+        NpcClass {
+            id: 7,
+            name: "guard".to_string(),
+            commonality,
+            next_tick,
+            active: 1,
+            unique: 0,
+            visit_minutes,
+            world: 1,
+            announce: 0,
+            arrival_message: None,
+            departure_message: None,
+        }
+    }
+
+    fn start() -> NaiveDateTime {
+        NaiveDate::from_ymd(2018, 9, 3).and_hms(0, 0, 0)
+    }
+
+    fn summary(instances: &[NewNpcInstance]) -> Vec<(NaiveDateTime, Option<String>)> {
+        instances
+            .iter()
+            .map(|i| (i.active_until, i.name.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn skipping_ticks_matches_tick_by_tick() {
+        let schedules = vec![NpcSchedule {
+            id: 1,
+            class: 7,
+            weekdays: ALL_WEEKDAYS,
+            start_minute: 8 * 60,
+            end_minute: 20 * 60,
+        }];
+        let names = vec![
+            NpcName {
+                id: 1,
+                class: 7,
+                kind: "syllable".to_string(),
+                value: "al".to_string(),
+            },
+            NpcName {
+                id: 2,
+                class: 7,
+                kind: "syllable".to_string(),
+                value: "dric".to_string(),
+            },
+        ];
+        let mut rng = ChaChaRng::from_seed([3; 32]);
+        for case in 0..200u32 {
+            let tick = Duration::seconds(i64::from(rng.gen_range(1u32, 600)));
+            let visit = rng.gen_range(1u32, 120) as i32;
+            let max = i64::from(visit) * 60 * 100 / tick.num_seconds();
+            let commonality = rng.gen_range(0u32, max as u32 + 1) as i32;
+            let pause = tick * rng.gen_range(0u32, 2000) as i32
+                + Duration::seconds(i64::from(rng.gen_range(0u32, 600)));
+            let schedules = if case % 2 == 0 { &schedules[..] } else { &[] };
+            let final_time = start() + pause;
+
+            let mut skipping = class(commonality, visit, start());
+            let skipped = create_instances(&mut skipping, schedules, &names, &final_time, &tick);
+
+            let mut stepping = class(commonality, visit, start());
+            let mut stepped = Vec::new();
+            while stepping.next_tick <= final_time {
+                let time = stepping.next_tick;
+                stepped.extend(create_instances(
+                    &mut stepping,
+                    schedules,
+                    &names,
+                    &time,
+                    &tick,
+                ));
+            }
+            stepped.retain(|i| i.active_until >= final_time);
+
+            assert_eq!(skipping.next_tick, stepping.next_tick, "case {}", case);
+            assert_eq!(summary(&skipped), summary(&stepped), "case {}", case);
+        }
+    }
+
+    fn set_clock(connection: &SqliteConnection, time: NaiveDateTime) {
+        let clock = GameClock {
+            anchor: time,
+            offset: Duration::zero(),
+            rate: 0f64,
+            paused: false,
+        };
+        clock.save(connection, 1).unwrap();
+    }
+
+    fn changes(changes: &[InstanceChange]) -> Vec<(i32, &str, NaiveDateTime)> {
+        changes
+            .iter()
+            .map(|c| (c.instance, c.class_name.as_str(), c.time))
+            .collect()
+    }
+
+    #[test]
+    fn update_reports_spawns_expirations_and_collapses() {
+        let connection = test_connection();
+        // 10 minute ticks and 20 minute visits at 200% spawn on every tick
+        connection
+            .batch_execute(
+                "INSERT INTO `constants` (`key`, `value`, `world`) \
+                 VALUES ('tick_seconds', '600', 1); \
+                 INSERT INTO `npc_classes` \
+                 (`name`, `commonality`, `next_tick`, `unique`, `visit_minutes`) \
+                 VALUES ('guard', 200, '2018-09-03 00:00:00', 0, 20), \
+                 ('king', 200, '2018-09-03 00:00:00', 1, 20); \
+                 INSERT INTO `npc_instances` (`class`, `active_until`) \
+                 VALUES (1, '2018-09-03 00:05:00');",
+            )
+            .unwrap();
+        set_clock(&connection, start() + Duration::minutes(25));
+
+        let report = update_instances(&connection, 1).unwrap();
+        // the visits rolled at 00:00 ended at 00:20, before the update, and are not created
+        let at = |minutes| start() + Duration::minutes(minutes);
+        assert_eq!(
+            changes(&report.spawned),
+            vec![
+                (2, "guard", at(10)),
+                (3, "guard", at(20)),
+                (4, "king", at(10)),
+                (5, "king", at(20)),
+            ]
+        );
+        assert_eq!(changes(&report.expired), vec![(1, "guard", at(5))]);
+        assert_eq!(changes(&report.collapsed), vec![(4, "king", at(25))]);
+
+        let events: Vec<NpcEvent> = npc_events::table
+            .order(npc_events::dsl::id)
+            .load(&connection)
+            .unwrap();
+        let logged = events
+            .iter()
+            .map(|e| (e.kind.as_str(), e.instance, e.class_name.as_str(), e.time))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            logged,
+            vec![
+                ("spawned", 2, "guard", at(10)),
+                ("spawned", 3, "guard", at(20)),
+                ("spawned", 4, "king", at(10)),
+                ("spawned", 5, "king", at(20)),
+                ("expired", 1, "guard", at(5)),
+                ("collapsed", 4, "king", at(25)),
+            ]
+        );
+        let remaining: Vec<i32> = npc_instances::table
+            .select(npc_instances::dsl::id)
+            .order(npc_instances::dsl::id)
+            .load(&connection)
+            .unwrap();
+        assert_eq!(remaining, vec![2, 3, 5]);
+
+        // a visit ending right at the update is still there
+        set_clock(&connection, at(30));
+        let report = update_instances(&connection, 1).unwrap();
+        assert_eq!(
+            changes(&report.spawned),
+            vec![(6, "guard", at(30)), (7, "king", at(30))]
+        );
+        assert!(report.expired.is_empty());
+        assert_eq!(changes(&report.collapsed), vec![(5, "king", at(30))]);
+    }
+
+    #[test]
+    fn rate_is_limited_by_visit_and_tick() {
+        let tick = Duration::seconds(10);
+        // a 20 minute visit holds 120 ticks, so up to 12000% can be kept present
+        assert!(check_rate(12000, 20, &tick).is_ok());
+        assert!(check_rate(12001, 20, &tick).is_err());
+        assert!(check_rate(0, 20, &tick).is_ok());
+        assert!(check_rate(-1, 20, &tick).is_err());
+        // a tick longer than the visit can not keep one npc present all the time
+        assert!(check_rate(50, 1, &Duration::seconds(120)).is_ok());
+        assert!(check_rate(51, 1, &Duration::seconds(120)).is_err());
+    }
+
+    fn event(kind: &str, class: i32, instance: i32, minutes: i64) -> NpcEvent {
+        NpcEvent {
+            id: 0,
+            world: 1,
+            kind: kind.to_string(),
+            class,
+            class_name: "king".to_string(),
+            instance,
+            time: start() + Duration::minutes(minutes),
+        }
+    }
+
+    fn arrivals(events: &[NpcEvent]) -> Vec<i32> {
+        events
+            .iter()
+            .filter(|event| event.kind == "spawned" && arrives(events, event))
+            .map(|event| event.instance)
+            .collect()
+    }
+
+    #[test]
+    fn a_visit_continuing_an_earlier_one_is_no_arrival() {
+        let events = vec![event("spawned", 1, 5, 10), event("collapsed", 1, 4, 25)];
+        assert_eq!(arrivals(&events), Vec::<i32>::new());
+    }
+
+    #[test]
+    fn collapsed_new_visits_arrive_once() {
+        let events = vec![
+            event("spawned", 1, 5, 10),
+            event("spawned", 1, 6, 20),
+            event("collapsed", 1, 5, 25),
+        ];
+        assert_eq!(arrivals(&events), vec![5]);
+    }
+
+    #[test]
+    fn collapses_only_touch_overlapping_visits() {
+        let events = vec![
+            event("collapsed", 1, 4, 5),
+            event("spawned", 1, 5, 10),
+            event("expired", 1, 5, 30),
+            event("spawned", 1, 6, 40),
+            event("spawned", 2, 7, 45),
+            event("spawned", 1, 8, 45),
+            event("collapsed", 1, 6, 50),
+        ];
+        assert_eq!(arrivals(&events), vec![5, 6, 7]);
+    }
+
+    #[test]
+    fn shortened_new_visits_collapse_into_the_old_one() {
+        // the visit time was shortened between the spawns, so 6 ends first and
+        // is the one collapsed
+        let events = vec![
+            event("spawned", 1, 5, 10),
+            event("spawned", 1, 6, 20),
+            event("collapsed", 1, 6, 25),
+            event("spawned", 1, 8, 30),
+            event("collapsed", 1, 8, 35),
+            event("expired", 1, 5, 70),
+        ];
+        assert_eq!(arrivals(&events), vec![5]);
+    }
+
+    fn name(kind: &str, value: &str) -> NpcName {
+        NpcName {
+            id: 0,
+            class: 7,
+            kind: kind.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    #[test]
+    fn names_only_depend_on_the_seed() {
+        let names = vec![
+            name("syllable", "al"),
+            name("syllable", "dric"),
+            name("syllable", "mo"),
+        ];
+        // two or three syllables, capitalized after the class
+        let syllables = ["al", "dric", "mo"];
+        let mut possible = Vec::new();
+        for a in &syllables {
+            for b in &syllables {
+                possible.push(format!("Guard {}", capitalize(&format!("{}{}", a, b))));
+                for c in &syllables {
+                    possible.push(format!("Guard {}", capitalize(&format!("{}{}{}", a, b, c))));
+                }
+            }
+        }
+        for seed in 0..50u8 {
+            let first = generate_name("guard", &names, &mut ChaChaRng::from_seed([seed; 32]));
+            let again = generate_name("guard", &names, &mut ChaChaRng::from_seed([seed; 32]));
+            assert_eq!(first, again);
+            let first = first.unwrap();
+            assert!(possible.contains(&first), "{}", first);
+        }
+        // stored names do not change, but a new rng draw order would rename new spawns
+        let mut rng = ChaChaRng::from_seed([0; 32]);
+        assert_eq!(
+            generate_name("guard", &names, &mut rng),
+            Some("Guard Dricmoal".to_string())
+        );
+    }
+
+    #[test]
+    fn whole_names_win_over_syllables() {
+        let names = vec![name("syllable", "al"), name("name", "Bob")];
+        let mut rng = ChaChaRng::from_seed([1; 32]);
+        assert_eq!(
+            generate_name("night watch", &names, &mut rng),
+            Some("Night watch Bob".to_string())
+        );
+        assert_eq!(generate_name("guard", &[], &mut rng), None);
+    }
+
+    #[test]
+    fn picks_stay_in_range_and_repeat() {
+        let mut rng = ChaChaRng::from_seed([2; 32]);
+        let picks = (0..100).map(|_| pick(&mut rng, 3)).collect::<Vec<_>>();
+        assert!(picks.iter().all(|&p| p < 3));
+        assert!((0..3).all(|n| picks.contains(&n)));
+        let mut rng = ChaChaRng::from_seed([2; 32]);
+        assert_eq!(picks, (0..100).map(|_| pick(&mut rng, 3)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn capitalize_only_touches_the_first_letter() {
+        assert_eq!(capitalize("aldric"), "Aldric");
+        assert_eq!(capitalize("éowyn mo"), "Éowyn mo");
+        assert_eq!(capitalize(""), "");
+    }
+}